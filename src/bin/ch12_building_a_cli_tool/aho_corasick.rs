@@ -0,0 +1,164 @@
+// Finds many literal patterns in one pass over a line, using the Aho-Corasick algorithm.
+// All of the patterns are stored in a single trie of chars. Each trie node also gets a
+// "fail" link to the longest suffix of it that is also in the trie, so when the next char
+// doesn't fit, the search falls back along those links instead of starting over.
+use super::unicode;
+use std::collections::{HashMap, VecDeque};
+
+/// An automaton that matches any of a set of patterns
+#[derive(Debug)]
+pub struct AhoCorasick {
+    states: Vec<State>,
+    // pattern lengths in chars, after case folding
+    lengths: Vec<usize>,
+    longest: usize,
+    case_insensitive: bool,
+    // set if one of the patterns is empty, since that matches everywhere
+    empty_pattern: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next: HashMap<char, usize>,
+    fail: usize,
+    // patterns that end here, including the ones reached through fail links
+    outputs: Vec<usize>,
+}
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S], case_insensitive: bool) -> AhoCorasick {
+        let mut states = vec![State::default()];
+        let mut lengths = vec![];
+        let mut empty_pattern = None;
+
+        // build the trie
+        for (id, pattern) in patterns.iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let chars: Vec<char> = if case_insensitive {
+                unicode::fold_str(pattern).chars().collect()
+            } else {
+                pattern.chars().collect()
+            };
+
+            if chars.is_empty() && empty_pattern.is_none() {
+                empty_pattern = Some(id);
+            }
+
+            let mut state = 0;
+            for c in &chars {
+                state = match states[state].next.get(c) {
+                    Some(&next) => next,
+                    None => {
+                        states.push(State::default());
+                        let next = states.len() - 1;
+                        states[state].next.insert(*c, next);
+                        next
+                    }
+                };
+            }
+            states[state].outputs.push(id);
+            lengths.push(chars.len());
+        }
+
+        // link each state to its longest proper suffix, breadth first so parents are done first
+        let mut queue: VecDeque<usize> = states[0].next.values().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let edges: Vec<(char, usize)> =
+                states[state].next.iter().map(|(c, s)| (*c, *s)).collect();
+
+            for (c, child) in edges {
+                let mut fail = states[state].fail;
+                let child_fail = loop {
+                    if let Some(&next) = states[fail].next.get(&c) {
+                        break next;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = states[fail].fail;
+                };
+
+                states[child].fail = child_fail;
+                let inherited = states[child_fail].outputs.clone();
+                states[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+
+        AhoCorasick {
+            longest: lengths.iter().copied().max().unwrap_or(0),
+            states,
+            lengths,
+            case_insensitive,
+            empty_pattern,
+        }
+    }
+
+    /// Returns (start, end, pattern) of the leftmost match at or after `start`.
+    /// When several patterns start at the same place the longest one wins.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize, usize)> {
+        if let Some(id) = self.empty_pattern {
+            return Some((start, start, id));
+        }
+
+        // byte range of the text char that produced each char fed to the automaton
+        let mut sources: Vec<(usize, usize)> = vec![];
+        let mut best: Option<(usize, usize, usize)> = None;
+        let mut state = 0;
+
+        for (i, c) in text[start..].char_indices() {
+            let begin = start + i;
+            let end = begin + c.len_utf8();
+
+            // stop once nothing found later could start before the best match so far
+            if let Some((best_start, _, _)) = best {
+                let earliest = (sources.len() + 1).saturating_sub(self.longest);
+                if sources.get(earliest).map_or(begin, |source| source.0) > best_start {
+                    break;
+                }
+            }
+
+            // a char may fold into more than one char
+            let mut folded = unicode::fold(c);
+            let mut exact = Some(c);
+            loop {
+                let c = if self.case_insensitive {
+                    folded.next()
+                } else {
+                    exact.take()
+                };
+                let c = match c {
+                    Some(c) => c,
+                    None => break,
+                };
+
+                sources.push((begin, end));
+                state = self.step(state, c);
+
+                for &id in &self.states[state].outputs {
+                    let found_start = sources[sources.len() - self.lengths[id]].0;
+                    let better = best.is_none_or(|(best_start, best_end, _)| {
+                        found_start < best_start || (found_start == best_start && end > best_end)
+                    });
+                    if better {
+                        best = Some((found_start, end, id));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.states[state].next.get(&c) {
+                return next;
+            }
+            if state == 0 {
+                return 0;
+            }
+            state = self.states[state].fail;
+        }
+    }
+}
@@ -0,0 +1,343 @@
+// Reads the files inside gzip (RFC 1952) and tar archives, so they're searched without
+// extracting them first. Both are found by their magic bytes, not by their names.
+// - gzip is a header, DEFLATE data, then a checksum and the size of what was compressed.
+//   Files can have several of these one after another, ex. from `cat a.gz b.gz`.
+// - tar is a 512 byte header for each file, followed by its contents padded to 512 bytes.
+// ex. $ cargo run --bin ch12_building_a_cli_tool -- -n ERROR ./logs/app.log.gz ./logs/old.tar.gz
+use super::deflate::{invalid, next_byte, Inflate};
+use std::io::{self, BufRead, Chain, Cursor, Read};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+// the "ustar" magic comes after the name, mode, owner, size and link fields of the header
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_BLOCK: u64 = 512;
+
+// gzip header flags
+const FLAG_HEADER_CRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Tar,
+}
+
+/// Finds the format from the first bytes of a file
+pub fn format(start: &[u8]) -> Option<Format> {
+    if start.starts_with(GZIP_MAGIC) {
+        Some(Format::Gzip)
+    } else if start[TAR_MAGIC_OFFSET.min(start.len())..].starts_with(TAR_MAGIC) {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+/// A reader with the bytes `detect` read put back in front of it
+pub type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads the start of a reader for its format, and returns a reader that reads all of it again.
+/// Pipes can hand over a few bytes at a time, so it keeps reading until there's enough to see
+/// the tar magic, or until the start can't be an archive. Text stops at its first line,
+/// so lines piped in one at a time are still searched as they come.
+pub fn detect<R: BufRead>(mut reader: R) -> io::Result<(Option<Format>, Peeked<R>)> {
+    let len = TAR_MAGIC_OFFSET + TAR_MAGIC.len();
+    let mut start = Vec::with_capacity(len);
+    while start.len() < len && could_be_tar(&start) {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let n = buf.len().min(len - start.len());
+        start.extend_from_slice(&buf[..n]);
+        reader.consume(n);
+    }
+    Ok((format(&start), Cursor::new(start).chain(reader)))
+}
+
+// a tar header starts with a name ended by a NUL, so a line that ends first is text
+fn could_be_tar(start: &[u8]) -> bool {
+    !start.starts_with(GZIP_MAGIC) && start.iter().find(|&&b| b == b'\n' || b == 0) != Some(&b'\n')
+}
+
+/// Decompresses a gzip file as it's read
+pub struct Gzip<R> {
+    inflate: Inflate<R>,
+    // of the member being read
+    crc: u32,
+    size: u32,
+    done: bool,
+}
+
+impl<R: BufRead> Gzip<R> {
+    pub fn new(mut reader: R) -> io::Result<Gzip<R>> {
+        read_header(&mut reader)?;
+        Ok(Gzip {
+            inflate: Inflate::new(reader),
+            crc: 0,
+            size: 0,
+            done: false,
+        })
+    }
+
+    // checks the end of a member against what was read, then moves on to the next member if any
+    fn finish_member(&mut self) -> io::Result<()> {
+        let mut trailer = [0; 8];
+        for byte in trailer.iter_mut() {
+            *byte = self.inflate.byte_after()?;
+        }
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != self.crc || size != self.size {
+            return Err(invalid("gzip checksum doesn't match"));
+        }
+
+        let reader = self.inflate.get_mut();
+        if reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+            read_header(reader)?;
+            self.inflate.restart();
+            self.crc = 0;
+            self.size = 0;
+        } else {
+            self.done = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Gzip<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done && !buf.is_empty() {
+            let n = self.inflate.read(buf)?;
+            if n > 0 {
+                self.crc = crc32(self.crc, &buf[..n]);
+                self.size = self.size.wrapping_add(n as u32);
+                return Ok(n);
+            }
+            self.finish_member()?;
+        }
+        Ok(0)
+    }
+}
+
+// skips the header of a gzip member, which only has the file's name and other details
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    let mut header = [0; 10];
+    reader.read_exact(&mut header)?;
+    if !header.starts_with(GZIP_MAGIC) {
+        return Err(invalid("not a gzip file"));
+    }
+    // DEFLATE is the only method
+    if header[2] != 8 {
+        return Err(invalid("unknown gzip compression method"));
+    }
+
+    let flags = header[3];
+    if flags & FLAG_EXTRA != 0 {
+        let len = u16::from_le_bytes([next_byte(reader)?, next_byte(reader)?]);
+        io::copy(&mut reader.take(len.into()), &mut io::sink())?;
+    }
+    // zero terminated strings
+    for flag in [FLAG_NAME, FLAG_COMMENT].iter() {
+        if flags & flag != 0 {
+            while next_byte(reader)? != 0 {}
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        reader.read_exact(&mut [0; 2])?;
+    }
+    Ok(())
+}
+
+/// Updates a CRC-32 checksum, the one gzip and zip use, with more bytes
+pub fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            // 0xedb88320 is the CRC-32 polynomial with its bits reversed
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Reads the files in a tar archive one after the other.
+/// After `next_file` finds a file, reading the archive reads the contents of that file.
+pub struct Tar<R> {
+    reader: R,
+    // what's left of the current file's contents, and the padding after them
+    left: u64,
+    padding: u64,
+}
+
+impl<R: Read> Tar<R> {
+    pub fn new(reader: R) -> Tar<R> {
+        Tar {
+            reader,
+            left: 0,
+            padding: 0,
+        }
+    }
+
+    /// Skips to the next regular file and returns its path, or `None` at the end of the archive.
+    /// Folders, links and other entries are skipped.
+    pub fn next_file(&mut self) -> io::Result<Option<String>> {
+        self.skip(self.left + self.padding)?;
+        self.left = 0;
+        self.padding = 0;
+        // set by an entry before the file, for names that don't fit in the header
+        let mut long_name = None;
+
+        loop {
+            let mut header = [0; TAR_BLOCK as usize];
+            if !read_block(&mut self.reader, &mut header)? || header.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+            check_header(&header)?;
+
+            let size = number(&header[124..136])?;
+            let padding = (TAR_BLOCK - size % TAR_BLOCK) % TAR_BLOCK;
+            match header[156] {
+                b'0' | b'\0' | b'7' => {
+                    self.left = size;
+                    self.padding = padding;
+                    return Ok(Some(long_name.unwrap_or_else(|| header_name(&header))));
+                }
+                // GNU's long name, the contents are the name of the next entry
+                b'L' => {
+                    let name = self.read_contents(size, padding)?;
+                    let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                    long_name = Some(String::from_utf8_lossy(name).into_owned());
+                }
+                // a POSIX extended header, which can hold the path of the next entry
+                b'x' => {
+                    let records = self.read_contents(size, padding)?;
+                    long_name = pax_path(&records).or(long_name);
+                }
+                _ => self.skip(size + padding)?,
+            }
+        }
+    }
+
+    fn read_contents(&mut self, size: u64, padding: u64) -> io::Result<Vec<u8>> {
+        let mut contents = vec![];
+        (&mut self.reader).take(size).read_to_end(&mut contents)?;
+        if (contents.len() as u64) < size {
+            return Err(invalid("tar archive ends early"));
+        }
+        self.skip(padding)?;
+        Ok(contents)
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(invalid("tar archive ends early"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Tar<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.left.min(usize::MAX as u64) as usize);
+        let n = self.reader.read(&mut buf[..len])?;
+        if n == 0 && len > 0 {
+            return Err(invalid("tar archive ends early"));
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+// reads a whole header, returns false if the archive ended before it
+fn read_block<R: Read>(reader: &mut R, block: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < block.len() {
+        match reader.read(&mut block[read..])? {
+            0 if read == 0 => return Ok(false),
+            0 => return Err(invalid("tar archive ends early")),
+            n => read += n,
+        }
+    }
+    Ok(true)
+}
+
+// the checksum is the sum of the header's bytes, counting its own field as spaces
+fn check_header(header: &[u8]) -> io::Result<()> {
+    let expected = number(&header[148..156])?;
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b })
+        .map(u64::from)
+        .sum();
+    if sum != expected {
+        return Err(invalid("invalid tar header"));
+    }
+    Ok(())
+}
+
+// Numbers are octal text ended by a space or NUL. Ones too big for that field are stored
+// in binary after a byte with its top bit set.
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let mut value = u64::from(field[0] & 0x7f);
+        for &b in &field[1..] {
+            // shifting would push the top byte out
+            if value >> 56 != 0 {
+                return Err(invalid("tar number too big"));
+            }
+            value = value << 8 | u64::from(b);
+        }
+        return Ok(value);
+    }
+
+    let text = String::from_utf8_lossy(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| invalid("invalid number in a tar header"))
+}
+
+// the name field, after the prefix field for longer paths in the ustar format
+fn header_name(header: &[u8]) -> String {
+    let field = |range: std::ops::Range<usize>| {
+        let bytes = &header[range];
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+    let name = field(0..100);
+    let prefix = if header[257..262] == *TAR_MAGIC {
+        field(345..500)
+    } else {
+        String::new()
+    };
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+// Extended headers are records like "30 path=some/very/long/name\n",
+// where the number is the length of the whole record
+fn pax_path(mut records: &[u8]) -> Option<String> {
+    let mut path = None;
+    while !records.is_empty() {
+        let space = records.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&records[..space]).ok()?.parse().ok()?;
+        let record = records.get(space + 1..len)?;
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        records = &records[len..];
+    }
+    path
+}
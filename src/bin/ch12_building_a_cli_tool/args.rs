@@ -0,0 +1,434 @@
+// A small command line parser for minigrep.
+// Every flag is listed once in `FLAGS`, which drives both parsing and `--help`.
+// Supports `-i`, bundled short flags (`-in`), `-A 3`/`-A3`, `--long`,
+// `--long value`/`--long=value`, and `--` to stop reading flags.
+use super::walk;
+use std::fmt;
+
+/// Whether a flag needs a value after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    None,
+    Required(&'static str),
+    // only given with `--flag=value`
+    Optional(&'static str),
+}
+
+/// A flag that minigrep understands
+pub struct Flag {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub value: Value,
+    pub help: &'static str,
+}
+
+pub const FLAGS: &[Flag] = &[
+    Flag {
+        short: Some('e'),
+        long: "regexp",
+        value: Value::Required("PATTERN"),
+        help: "Search for PATTERN, can be repeated to search for several",
+    },
+    Flag {
+        short: Some('f'),
+        long: "file",
+        value: Value::Required("FILE"),
+        help: "Search for every pattern in FILE, one per line",
+    },
+    Flag {
+        short: Some('i'),
+        long: "ignore-case",
+        value: Value::None,
+        help: "Match upper and lower case the same",
+    },
+    Flag {
+        short: Some('s'),
+        long: "case-sensitive",
+        value: Value::None,
+        help: "Match case exactly, even if CASE_INSENSITIVE is set",
+    },
+    Flag {
+        short: None,
+        long: "regex",
+        value: Value::None,
+        help: "Treat the query as a regular expression",
+    },
+    Flag {
+        short: None,
+        long: "boolean",
+        value: Value::None,
+        help: "Treat the query as words and \"phrases\" combined with AND, OR, NOT and ()",
+    },
+    Flag {
+        short: Some('w'),
+        long: "word-regexp",
+        value: Value::None,
+        help: "Only match whole words",
+    },
+    Flag {
+        short: Some('x'),
+        long: "line-regexp",
+        value: Value::None,
+        help: "Only match whole lines",
+    },
+    Flag {
+        short: Some('U'),
+        long: "multiline",
+        value: Value::None,
+        help: "Let matches span lines, printing every line a match is on",
+    },
+    Flag {
+        short: None,
+        long: "flex-space",
+        value: Value::None,
+        help: "Match any run of spaces and newlines where the query has spaces",
+    },
+    Flag {
+        short: None,
+        long: "fuzzy",
+        value: Value::Required("K"),
+        help: "Match text up to K inserted, deleted or changed chars away from the query",
+    },
+    Flag {
+        short: None,
+        long: "rank",
+        value: Value::Optional("K"),
+        help: "Print the K files or records that best match the query's words, 10 by default",
+    },
+    Flag {
+        short: Some('v'),
+        long: "invert-match",
+        value: Value::None,
+        help: "Select the lines that don't match",
+    },
+    Flag {
+        short: Some('c'),
+        long: "count",
+        value: Value::None,
+        help: "Print how many lines were selected in each file",
+    },
+    Flag {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: Value::None,
+        help: "Print only the paths of files with a selected line",
+    },
+    Flag {
+        short: Some('L'),
+        long: "files-without-match",
+        value: Value::None,
+        help: "Print only the paths of files without a selected line",
+    },
+    Flag {
+        short: Some('q'),
+        long: "quiet",
+        value: Value::None,
+        help: "Print nothing, exit with 0 as soon as a line is selected",
+    },
+    Flag {
+        short: None,
+        long: "json",
+        value: Value::None,
+        help: "Print every event as a line of JSON",
+    },
+    Flag {
+        short: Some('m'),
+        long: "max-count",
+        value: Value::Required("NUM"),
+        help: "Stop reading a file after NUM selected lines",
+    },
+    Flag {
+        short: Some('n'),
+        long: "line-number",
+        value: Value::None,
+        help: "Show the line number of each line",
+    },
+    Flag {
+        short: None,
+        long: "column",
+        value: Value::None,
+        help: "Show the column of the first match (implies -n)",
+    },
+    Flag {
+        short: Some('A'),
+        long: "after-context",
+        value: Value::Required("NUM"),
+        help: "Show NUM lines after each match",
+    },
+    Flag {
+        short: Some('B'),
+        long: "before-context",
+        value: Value::Required("NUM"),
+        help: "Show NUM lines before each match",
+    },
+    Flag {
+        short: Some('C'),
+        long: "context",
+        value: Value::Required("NUM"),
+        help: "Show NUM lines before and after each match",
+    },
+    Flag {
+        short: None,
+        long: "color",
+        value: Value::Optional("WHEN"),
+        help: "Highlight matches: auto, always or never",
+    },
+    Flag {
+        short: None,
+        long: "encoding",
+        value: Value::Required("NAME"),
+        help: "Read files as utf-8, utf-16le, utf-16be, latin1 or windows-1252",
+    },
+    Flag {
+        short: None,
+        long: "normalize",
+        value: Value::Required("FORM"),
+        help: "Match accents however they're written, using Unicode form nfc or nfd",
+    },
+    Flag {
+        short: Some('p'),
+        long: "paragraph",
+        value: Value::None,
+        help: "Search paragraphs separated by blank lines instead of lines",
+    },
+    Flag {
+        short: Some('z'),
+        long: "null-data",
+        value: Value::None,
+        help: "Search records that end with a NUL byte instead of lines",
+    },
+    Flag {
+        short: None,
+        long: "record-separator",
+        value: Value::Required("SEP"),
+        help: "Search records that end with SEP, which can use \\n, \\t and \\0",
+    },
+    Flag {
+        short: None,
+        long: "replace",
+        value: Value::Required("TEXT"),
+        help: "Print each match replaced with TEXT",
+    },
+    Flag {
+        short: None,
+        long: "in-place",
+        value: Value::Optional("SUFFIX"),
+        help: "Write the replacements back to the files, keeping a copy of each with SUFFIX",
+    },
+    Flag {
+        short: None,
+        long: "dry-run",
+        value: Value::None,
+        help: "Print a diff of what --in-place would change instead",
+    },
+    Flag {
+        short: Some('g'),
+        long: "glob",
+        value: Value::Required("GLOB"),
+        help: "Only search files in folders that match GLOB, or skip them with !GLOB",
+    },
+    Flag {
+        short: Some('t'),
+        long: "type",
+        value: Value::Required("TYPE"),
+        help: "Only search files in folders of TYPE, ex. rust or md",
+    },
+    Flag {
+        short: Some('T'),
+        long: "type-not",
+        value: Value::Required("TYPE"),
+        help: "Don't search files in folders of TYPE",
+    },
+    Flag {
+        short: None,
+        long: "type-list",
+        value: Value::None,
+        help: "Print the file types for --type and --type-not",
+    },
+    Flag {
+        short: None,
+        long: "index",
+        value: Value::None,
+        help: "Skip the files that --build-index found can't match",
+    },
+    Flag {
+        short: None,
+        long: "build-index",
+        value: Value::Required("DIR"),
+        help: "Write the index of DIR that --index reads, instead of searching",
+    },
+    Flag {
+        short: None,
+        long: "interactive",
+        value: Value::None,
+        help: "Browse the matches on a full screen, searching again as the query is edited",
+    },
+    Flag {
+        short: None,
+        long: "watch",
+        value: Value::None,
+        help: "Search again whenever a file changes, until interrupted",
+    },
+    Flag {
+        short: None,
+        long: "follow",
+        value: Value::None,
+        help: "Keep searching the lines appended to each file, like `tail -f`",
+    },
+    Flag {
+        short: Some('j'),
+        long: "threads",
+        value: Value::Required("NUM"),
+        help: "Search NUM files at once, 0 uses one thread per core",
+    },
+    Flag {
+        short: Some('h'),
+        long: "help",
+        value: Value::None,
+        help: "Print this help and exit",
+    },
+    Flag {
+        short: Some('V'),
+        long: "version",
+        value: Value::None,
+        help: "Print the version and exit",
+    },
+];
+
+/// Why the command line couldn't be turned into a search
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    // not real errors, main prints these and exits successfully
+    Help,
+    Version,
+    TypeList,
+    Invalid(String),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgsError::Help => write!(f, "{}", help()),
+            ArgsError::Version => write!(f, "minigrep {}", env!("CARGO_PKG_VERSION")),
+            ArgsError::TypeList => write!(f, "{}", walk::type_list()),
+            ArgsError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<&str> for ArgsError {
+    fn from(message: &str) -> ArgsError {
+        ArgsError::Invalid(message.to_string())
+    }
+}
+
+/// A command line split into flags and everything else
+#[derive(Debug, Default)]
+pub struct ParsedArgs<'a> {
+    // (long name, value) in the order they were given
+    pub flags: Vec<(&'static str, Option<&'a str>)>,
+    pub positional: Vec<&'a str>,
+}
+
+/// Splits the args (without the program name) into flags and positional args
+pub fn parse(args: &[String]) -> Result<ParsedArgs<'_>, ArgsError> {
+    let mut parsed = ParsedArgs::default();
+    let mut args = args.iter().map(String::as_str);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.positional.extend(args.by_ref());
+        } else if let Some(long) = arg.strip_prefix("--") {
+            // --name=value or --name
+            let (name, inline) = match long.find('=') {
+                Some(i) => (&long[..i], Some(&long[i + 1..])),
+                None => (long, None),
+            };
+            let flag = FLAGS
+                .iter()
+                .find(|flag| flag.long == name)
+                .ok_or_else(|| unknown(arg))?;
+
+            let value = match (flag.value, inline) {
+                (Value::None, Some(_)) => {
+                    return Err(ArgsError::Invalid(format!(
+                        "--{} doesn't take a value",
+                        name
+                    )))
+                }
+                (Value::Required(_), None) => Some(required_value(flag, args.next())?),
+                (_, inline) => inline,
+            };
+            parsed.flags.push((flag.long, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // one or more short flags bundled together, ex. -in or -A3
+            for (i, c) in arg.char_indices().skip(1) {
+                let flag = FLAGS
+                    .iter()
+                    .find(|flag| flag.short == Some(c))
+                    .ok_or_else(|| unknown(&format!("-{}", c)))?;
+
+                if let Value::Required(_) = flag.value {
+                    let rest = &arg[i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                    } else {
+                        Some(rest)
+                    };
+                    parsed
+                        .flags
+                        .push((flag.long, Some(required_value(flag, value)?)));
+                    break;
+                }
+                parsed.flags.push((flag.long, None));
+            }
+        } else {
+            parsed.positional.push(arg);
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn required_value<'a>(flag: &Flag, value: Option<&'a str>) -> Result<&'a str, ArgsError> {
+    value.ok_or_else(|| ArgsError::Invalid(format!("--{} needs a value", flag.long)))
+}
+
+fn unknown(flag: &str) -> ArgsError {
+    ArgsError::Invalid(format!(
+        "unknown flag '{}', see --help for the list of flags",
+        flag
+    ))
+}
+
+/// Parses a flag's value as a number
+pub fn number(name: &str, value: Option<&str>) -> Result<usize, ArgsError> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ArgsError::Invalid(format!("--{} needs a whole number", name)))
+}
+
+/// Builds the `--help` text from the list of flags
+pub fn help() -> String {
+    let mut text = String::from(
+        "Search for QUERY in each PATH, or standard input when no PATH (or `-`) is given.\n\n\
+         Usage: minigrep [OPTIONS] QUERY [PATH]...\n       minigrep [OPTIONS] -e PATTERN... [PATH]...\n       \
+         minigrep --build-index DIR\n\nOptions:\n",
+    );
+
+    for flag in FLAGS {
+        let short = flag
+            .short
+            .map_or(String::from("    "), |c| format!("-{}, ", c));
+        let long = match flag.value {
+            Value::None => flag.long.to_string(),
+            Value::Required(name) => format!("{} {}", flag.long, name),
+            Value::Optional(name) => format!("{}[={}]", flag.long, name),
+        };
+        text.push_str(&format!("  {}--{:<24} {}\n", short, long, flag.help));
+    }
+
+    text.push_str("\nSet CASE_INSENSITIVE to ignore case by default.\n");
+    text.push_str("Exits with 0 if a line was selected, 1 if none were, and 2 on errors.");
+    text
+}
@@ -0,0 +1,360 @@
+// A DEFLATE decoder (RFC 1951), the compression inside gzip files.
+// Compressed data is a series of blocks. Each is stored as is, or Huffman coded with a fixed
+// or a dynamic set of codes. Huffman coded blocks are made of literal bytes and
+// (length, distance) pairs that copy output from up to 32K bytes back.
+// Output is produced as it's read, so only that window of output is kept in memory.
+use std::io::{self, BufRead, Read};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MAX_BITS: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+
+// the shortest length and number of extra bits of length codes 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// the same for distance codes 0 to 29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order the code lengths of a dynamic block's code length codes are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Reads a byte stream a few bits at a time, starting from the lowest bit of each byte
+struct Bits<R> {
+    reader: R,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: BufRead> Bits<R> {
+    fn read(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = next_byte(&mut self.reader)?;
+            self.bits |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1 << n) - 1);
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    // stored blocks and whatever follows the compressed data start on a whole byte
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.bits >>= partial;
+        self.count -= partial;
+    }
+
+    // the next whole byte, which may already be in the bits read ahead
+    fn byte(&mut self) -> io::Result<u8> {
+        self.align();
+        if self.count >= 8 {
+            return Ok(self.read(8)? as u8);
+        }
+        next_byte(&mut self.reader)
+    }
+}
+
+/// Reads one byte, it's an error if there are none left
+pub fn next_byte<R: BufRead>(reader: &mut R) -> io::Result<u8> {
+    let byte = *reader.fill_buf()?.first().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::UnexpectedEof, "compressed data ends early")
+    })?;
+    reader.consume(1);
+    Ok(byte)
+}
+
+// A canonical Huffman code, stored as how many codes there are of each length
+// and the symbols in the order of their codes
+#[derive(Debug)]
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    // builds the code from the length of each symbol's code, 0 for symbols that aren't used
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // more codes of a length than there's room for can't be decoded
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - i32::from(count);
+            if left < 0 {
+                return Err(invalid("invalid Huffman code lengths"));
+            }
+        }
+
+        let mut offsets = [0; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length] as usize;
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1]];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize]] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    // Reads a code one bit at a time. The codes of each length are consecutive numbers,
+    // so a code is found once it's below the last code of its length.
+    fn decode<R: BufRead>(&self, bits: &mut Bits<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+enum Symbol {
+    Literal(u8),
+    // (length, distance)
+    Copy(usize, usize),
+    EndOfBlock,
+}
+
+#[derive(Debug)]
+enum Block {
+    // the header of the next block is still to be read
+    Start,
+    // bytes left in a stored block
+    Stored(usize),
+    // the codes for literals and lengths, and for distances
+    Huffman(Huffman, Huffman),
+    Done,
+}
+
+/// Decompresses DEFLATE data as it's read
+pub struct Inflate<R> {
+    bits: Bits<R>,
+    // the last 32K of output, for copies to read from
+    window: Vec<u8>,
+    written: usize,
+    block: Block,
+    last_block: bool,
+    // the (length, distance) of a copy that didn't fit in the last read
+    copy: Option<(usize, usize)>,
+}
+
+impl<R: BufRead> Inflate<R> {
+    pub fn new(reader: R) -> Inflate<R> {
+        Inflate {
+            bits: Bits {
+                reader,
+                bits: 0,
+                count: 0,
+            },
+            window: vec![0; WINDOW_SIZE],
+            written: 0,
+            block: Block::Start,
+            last_block: false,
+            copy: None,
+        }
+    }
+
+    /// Reads a byte that follows the compressed data, ex. a gzip trailer
+    pub fn byte_after(&mut self) -> io::Result<u8> {
+        self.bits.byte()
+    }
+
+    /// The reader underneath, once the bytes after the compressed data have been read
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.bits.reader
+    }
+
+    /// Starts on another stream from the same reader
+    pub fn restart(&mut self) {
+        self.written = 0;
+        self.block = Block::Start;
+        self.last_block = false;
+        self.copy = None;
+    }
+
+    fn start_block(&mut self) -> io::Result<()> {
+        self.last_block = self.bits.read(1)? == 1;
+        self.block = match self.bits.read(2)? {
+            0 => {
+                self.bits.align();
+                let mut header = [0; 4];
+                for byte in header.iter_mut() {
+                    *byte = self.bits.byte()?;
+                }
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if len != !complement {
+                    return Err(invalid("invalid stored block length"));
+                }
+                Block::Stored(len as usize)
+            }
+            1 => {
+                let mut lengths = [0; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                Block::Huffman(Huffman::new(&lengths)?, Huffman::new(&[5; 30])?)
+            }
+            2 => self.dynamic_codes()?,
+            _ => return Err(invalid("invalid block type")),
+        };
+        Ok(())
+    }
+
+    // a dynamic block starts with the lengths of its codes, which are Huffman coded themselves
+    fn dynamic_codes(&mut self) -> io::Result<Block> {
+        let literals = self.bits.read(5)? as usize + 257;
+        let distances = self.bits.read(5)? as usize + 1;
+        let code_lengths = self.bits.read(4)? as usize + 4;
+
+        let mut lengths = [0; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[symbol] = self.bits.read(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&lengths)?;
+
+        let mut lengths = vec![];
+        while lengths.len() < literals + distances {
+            let (length, repeat) = match code_length_code.decode(&mut self.bits)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths
+                        .last()
+                        .ok_or_else(|| invalid("repeated code length with nothing before it"))?;
+                    (previous, 3 + self.bits.read(2)?)
+                }
+                17 => (0, 3 + self.bits.read(3)?),
+                _ => (0, 11 + self.bits.read(7)?),
+            };
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths.len() > literals + distances {
+            return Err(invalid("too many code lengths"));
+        }
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err(invalid("no code for the end of the block"));
+        }
+
+        let (literal_lengths, distance_lengths) = lengths.split_at(literals);
+        Ok(Block::Huffman(
+            Huffman::new(literal_lengths)?,
+            Huffman::new(distance_lengths)?,
+        ))
+    }
+
+    fn next_symbol(&mut self) -> io::Result<Symbol> {
+        let (literals, distances) = match &self.block {
+            Block::Huffman(literals, distances) => (literals, distances),
+            _ => unreachable!("only Huffman blocks have symbols"),
+        };
+
+        let symbol = literals.decode(&mut self.bits)?;
+        if symbol < END_OF_BLOCK {
+            return Ok(Symbol::Literal(symbol as u8));
+        } else if symbol == END_OF_BLOCK {
+            return Ok(Symbol::EndOfBlock);
+        }
+
+        let code = (symbol - 257) as usize;
+        if code >= LENGTH_BASE.len() {
+            return Err(invalid("invalid length code"));
+        }
+        let length =
+            LENGTH_BASE[code] as usize + self.bits.read(LENGTH_EXTRA[code].into())? as usize;
+        let code = distances.decode(&mut self.bits)? as usize;
+        if code >= DISTANCE_BASE.len() {
+            return Err(invalid("invalid distance code"));
+        }
+        let distance =
+            DISTANCE_BASE[code] as usize + self.bits.read(DISTANCE_EXTRA[code].into())? as usize;
+        if distance > self.written.min(WINDOW_SIZE) {
+            return Err(invalid("copy from before the start of the data"));
+        }
+        Ok(Symbol::Copy(length, distance))
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.window[self.written % WINDOW_SIZE] = byte;
+        self.written += 1;
+    }
+}
+
+impl<R: BufRead> Read for Inflate<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            if let Some((length, distance)) = self.copy.take() {
+                let len = length.min(buf.len() - n);
+                for out in &mut buf[n..n + len] {
+                    let byte = self.window[(self.written - distance) % WINDOW_SIZE];
+                    self.push(byte);
+                    *out = byte;
+                }
+                n += len;
+                if len < length {
+                    self.copy = Some((length - len, distance));
+                }
+                continue;
+            }
+
+            match self.block {
+                Block::Done => break,
+                Block::Start if self.last_block => {
+                    self.bits.align();
+                    self.block = Block::Done;
+                }
+                Block::Start => self.start_block()?,
+                Block::Stored(0) => self.block = Block::Start,
+                Block::Stored(left) => {
+                    let byte = self.bits.byte()?;
+                    self.push(byte);
+                    buf[n] = byte;
+                    n += 1;
+                    self.block = Block::Stored(left - 1);
+                }
+                Block::Huffman(..) => match self.next_symbol()? {
+                    Symbol::Literal(byte) => {
+                        self.push(byte);
+                        buf[n] = byte;
+                        n += 1;
+                    }
+                    Symbol::Copy(length, distance) => self.copy = Some((length, distance)),
+                    Symbol::EndOfBlock => self.block = Block::Start,
+                },
+            }
+        }
+        Ok(n)
+    }
+}
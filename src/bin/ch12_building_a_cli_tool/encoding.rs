@@ -0,0 +1,211 @@
+// Turns input in other text encodings into UTF-8 so the rest of minigrep only deals with one.
+// - UTF-16 files are found by their byte order mark (BOM) and decoded transparently
+// - Latin-1 and Windows-1252 have to be asked for with `--encoding`
+// - anything else is read as UTF-8, where invalid bytes are swapped for a placeholder
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Stands in for each invalid UTF-8 byte. It's a single byte itself, so match offsets
+/// in the decoded line are also offsets into the original bytes.
+pub const INVALID_BYTE: char = '\u{1a}';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Result<Encoding, &'static str> {
+        match name.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Ok(Encoding::Windows1252),
+            _ => Err("--encoding must be utf-8, utf-16le, utf-16be, latin1 or windows-1252"),
+        }
+    }
+}
+
+/// Wraps a reader so it produces UTF-8, using the BOM when no encoding is given
+pub fn decode<'r, R: BufRead + 'r>(
+    mut reader: R,
+    encoding: Option<Encoding>,
+) -> io::Result<Box<dyn BufRead + 'r>> {
+    let (encoding, bom_len) = detect(reader.fill_buf()?, encoding);
+    reader.consume(bom_len);
+
+    if encoding == Encoding::Utf8 {
+        Ok(Box::new(reader))
+    } else {
+        Ok(Box::new(BufReader::new(DecodeReader::new(
+            reader, encoding,
+        ))))
+    }
+}
+
+/// Finds the encoding of text that starts with `start`, and how many bytes its BOM takes
+pub fn detect(start: &[u8], encoding: Option<Encoding>) -> (Encoding, usize) {
+    let (found, bom_len) = if start.starts_with(&[0xef, 0xbb, 0xbf]) {
+        (Some(Encoding::Utf8), 3)
+    } else if start.starts_with(&[0xff, 0xfe]) {
+        (Some(Encoding::Utf16Le), 2)
+    } else if start.starts_with(&[0xfe, 0xff]) {
+        (Some(Encoding::Utf16Be), 2)
+    } else {
+        (None, 0)
+    };
+
+    // a BOM is only skipped when it agrees with the encoding that was asked for
+    match found {
+        Some(found) if encoding.is_none_or(|e| e == found) => (found, bom_len),
+        _ => (encoding.unwrap_or(Encoding::Utf8), 0),
+    }
+}
+
+/// Decodes a UTF-8 line, replacing each invalid byte with `INVALID_BYTE`
+pub fn lossy_line(bytes: &[u8]) -> Cow<'_, str> {
+    let mut rest = match std::str::from_utf8(bytes) {
+        Ok(text) => return Cow::Borrowed(text),
+        Err(_) => bytes,
+    };
+
+    let mut text = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return Cow::Owned(text);
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+
+                // a cut off char at the end has no error length
+                let invalid = e.error_len().unwrap_or(after.len());
+                for _ in 0..invalid {
+                    text.push(INVALID_BYTE);
+                }
+                rest = &after[invalid..];
+            }
+        }
+    }
+}
+
+/// A reader that converts another encoding into UTF-8 as it goes
+pub struct DecodeReader<R: Read> {
+    inner: R,
+    encoding: Encoding,
+    // decoded text that hasn't been read yet
+    decoded: Vec<u8>,
+    position: usize,
+    // bytes left over from the last read that didn't make a full char
+    partial: Vec<u8>,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub fn new(inner: R, encoding: Encoding) -> DecodeReader<R> {
+        DecodeReader {
+            inner,
+            encoding,
+            decoded: vec![],
+            position: 0,
+            partial: vec![],
+        }
+    }
+
+    // reads and decodes the next chunk, returns false at the end of the input
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8192];
+        let read = self.inner.read(&mut chunk)?;
+
+        self.partial.extend_from_slice(&chunk[..read]);
+        let encoding = self.encoding;
+        self.decoded = match encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut text = String::new();
+                let used = decode_utf16(&self.partial, encoding, read == 0, &mut text);
+                self.partial.drain(..used);
+                text.into_bytes()
+            }
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                let text: String = self
+                    .partial
+                    .drain(..)
+                    .map(|b| single_byte_char(b, encoding))
+                    .collect();
+                text.into_bytes()
+            }
+            // already UTF-8, invalid bytes are left for `lossy_line` to deal with
+            Encoding::Utf8 => std::mem::take(&mut self.partial),
+        };
+        self.position = 0;
+        Ok(read > 0 || !self.decoded.is_empty())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.decoded[self.position..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+// Decodes as many UTF-16 code units as possible and returns how many bytes were used.
+// A trailing odd byte or high surrogate waits for the next chunk, unless it's the end.
+fn decode_utf16(bytes: &[u8], encoding: Encoding, at_end: bool, text: &mut String) -> usize {
+    let mut units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| match encoding {
+            Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+            _ => u16::from_be_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+
+    let mut used = units.len() * 2;
+    if !at_end
+        && units
+            .last()
+            .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+    {
+        units.pop();
+        used -= 2;
+    }
+
+    for c in char::decode_utf16(units) {
+        text.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+    }
+
+    if at_end && used < bytes.len() {
+        text.push(char::REPLACEMENT_CHARACTER);
+        used = bytes.len();
+    }
+    used
+}
+
+// Windows-1252 is Latin-1 with printable chars in place of most of 0x80-0x9f
+fn single_byte_char(byte: u8, encoding: Encoding) -> char {
+    const WINDOWS_1252: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+
+    match byte {
+        0x80..=0x9f if encoding == Encoding::Windows1252 => WINDOWS_1252[byte as usize - 0x80],
+        _ => byte as char,
+    }
+}
@@ -0,0 +1,192 @@
+// Approximate matching, for finding words even when they're misspelled.
+// Uses Myers' bit-parallel algorithm: each bit of a word stands for one char of the query, so a
+// char of the line updates the edit distance of every prefix of the query at once.
+// ex. "frog" is 1 edit away from "frg" (deletion), "frig" (substitution) and "frogs" (insertion)
+use super::unicode;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The longest query that fits into the bits of a word
+pub const MAX_QUERY_LEN: usize = 64;
+
+/// A query prepared for finding substrings within an edit distance of it
+#[derive(Debug)]
+pub struct Fuzzy {
+    // case folded when ignoring case
+    chars: Vec<char>,
+    max_distance: usize,
+    ignore_case: bool,
+    // which positions of the query each char is at, for reading forward and backward
+    forward: Positions,
+    backward: Positions,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max_distance: usize, ignore_case: bool) -> Result<Fuzzy, String> {
+        let chars: Vec<char> = if ignore_case {
+            unicode::fold_str(query).chars().collect()
+        } else {
+            query.chars().collect()
+        };
+        if chars.len() > MAX_QUERY_LEN {
+            return Err(format!(
+                "--fuzzy queries can't be longer than {} chars",
+                MAX_QUERY_LEN
+            ));
+        }
+
+        Ok(Fuzzy {
+            forward: Positions::new(chars.iter()),
+            backward: Positions::new(chars.iter().rev()),
+            chars,
+            max_distance,
+            ignore_case,
+        })
+    }
+
+    /// Returns the byte range and edit distance of the first match at or after `start`.
+    /// A match is extended while that lowers its distance, so "frog" in "frogs" isn't cut to "fro".
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize, usize)> {
+        let len = self.chars.len();
+        // the query can be deleted entirely
+        if len <= self.max_distance {
+            return Some((start, start, len));
+        }
+
+        let text = self.line_chars(&line[start..]);
+        let (end, distance) = self.find_end(&text)?;
+        let begin = self.find_start(&text[..=end], distance);
+        Some((
+            start + text[begin].1.start,
+            start + text[end].1.end,
+            distance,
+        ))
+    }
+
+    // the chars to compare, with the bytes of the line each came from
+    fn line_chars(&self, line: &str) -> Vec<(char, Range<usize>)> {
+        let mut chars = Vec::with_capacity(line.len());
+        for (i, c) in line.char_indices() {
+            let source = i..i + c.len_utf8();
+            if self.ignore_case {
+                chars.extend(unicode::fold(c).map(|folded| (folded, source.clone())));
+            } else {
+                chars.push((c, source));
+            }
+        }
+        chars
+    }
+
+    // Finds where the first good enough match ends, and its distance.
+    // Any prefix of the line can be skipped for free, so the bits say how far the query is
+    // from ending at each char.
+    fn find_end(&self, text: &[(char, Range<usize>)]) -> Option<(usize, usize)> {
+        let mut columns = Columns::new(self.chars.len());
+        let mut best: Option<(usize, usize)> = None;
+
+        for (i, (c, _)) in text.iter().enumerate() {
+            let distance = columns.advance(self.forward.get(*c), false);
+            if best.is_some_and(|(_, best)| distance >= best) {
+                break;
+            }
+            if distance <= self.max_distance {
+                best = Some((i, distance));
+                if distance == 0 {
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+
+    // Reads back from the end of a match to where the shortest match with its distance starts.
+    // Now nothing can be skipped, so the bits say how far the query is from the chars read so far.
+    fn find_start(&self, text: &[(char, Range<usize>)], distance: usize) -> usize {
+        let mut columns = Columns::new(self.chars.len());
+        for (i, (c, _)) in text.iter().rev().enumerate() {
+            if columns.advance(self.backward.get(*c), true) <= distance {
+                return text.len() - 1 - i;
+            }
+        }
+        0
+    }
+}
+
+// A bit for every position of each char in the query, ASCII is looked up without hashing
+#[derive(Debug)]
+struct Positions {
+    ascii: [u64; 128],
+    other: HashMap<char, u64>,
+}
+
+impl Positions {
+    fn new<'a, I: Iterator<Item = &'a char>>(chars: I) -> Positions {
+        let mut positions = Positions {
+            ascii: [0; 128],
+            other: HashMap::new(),
+        };
+        for (i, &c) in chars.enumerate() {
+            let bit = 1 << i;
+            if c.is_ascii() {
+                positions.ascii[c as usize] |= bit;
+            } else {
+                *positions.other.entry(c).or_insert(0) |= bit;
+            }
+        }
+        positions
+    }
+
+    fn get(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            self.ascii[c as usize]
+        } else {
+            self.other.get(&c).copied().unwrap_or(0)
+        }
+    }
+}
+
+// The last column of the edit distance table, stored as the +1/-1 steps between its rows
+struct Columns {
+    plus: u64,
+    minus: u64,
+    // the bit of the last row, which is the distance of the whole query
+    last: u64,
+    distance: usize,
+}
+
+impl Columns {
+    fn new(len: usize) -> Columns {
+        Columns {
+            plus: !0,
+            minus: 0,
+            last: 1 << (len - 1),
+            distance: len,
+        }
+    }
+
+    // Moves to the next char of the text, where `matches` has a bit for each query position that
+    // has the same char. Returns the distance of the whole query.
+    // `anchored` counts the chars of the text before the query, instead of skipping them.
+    fn advance(&mut self, matches: u64, anchored: bool) -> usize {
+        let x_vertical = matches | self.minus;
+        let x_horizontal = ((matches & self.plus).wrapping_add(self.plus) ^ self.plus) | matches;
+        let mut plus_horizontal = self.minus | !(x_horizontal | self.plus);
+        let mut minus_horizontal = self.plus & x_horizontal;
+
+        if plus_horizontal & self.last != 0 {
+            self.distance += 1;
+        } else if minus_horizontal & self.last != 0 {
+            self.distance -= 1;
+        }
+
+        plus_horizontal <<= 1;
+        minus_horizontal <<= 1;
+        if anchored {
+            plus_horizontal |= 1;
+        }
+        self.plus = minus_horizontal | !(x_vertical | plus_horizontal);
+        self.minus = plus_horizontal & x_vertical;
+        self.distance
+    }
+}
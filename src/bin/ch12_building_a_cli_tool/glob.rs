@@ -0,0 +1,149 @@
+// Shell-style wildcard patterns for matching paths, as used in `.gitignore` files and `--glob`.
+// - `*` matches anything except `/`
+// - `?` matches one char except `/`
+// - `[abc]`, `[a-z]` match one of the chars, `[!a-z]` or `[^a-z]` one char that isn't
+// - `**` matches anything, including `/`
+// - `**/` matches zero or more directories
+// - `\` escapes the next char
+
+/// A compiled wildcard pattern
+#[derive(Debug, Clone)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    // inclusive ranges, a single char is a range of one
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Star,
+    DoubleStar,
+    // `**/`, which can also match nothing at all
+    Dirs,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = vec![];
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let after_slash = i == 0 || chars[i - 1] == '/';
+                    if after_slash && chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::Dirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::DoubleStar);
+                        i += 2;
+                    }
+                    continue;
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::AnyChar),
+                // a `[` that's never closed is only a `[`
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((class, len)) => {
+                        tokens.push(class);
+                        i += len;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                },
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    tokens.push(Token::Char(chars[i]));
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+
+        Glob { tokens }
+    }
+
+    /// Checks if the whole path matches. Paths should use `/` as the separator.
+    pub fn is_match(&self, path: &str) -> bool {
+        let text: Vec<char> = path.chars().collect();
+
+        // remember results of (token, char) pairs so stars can't blow up
+        let mut memo = vec![None; (self.tokens.len() + 1) * (text.len() + 1)];
+        self.match_from(0, 0, &text, &mut memo)
+    }
+
+    fn match_from(&self, t: usize, s: usize, text: &[char], memo: &mut [Option<bool>]) -> bool {
+        let key = t * (text.len() + 1) + s;
+        if let Some(result) = memo[key] {
+            return result;
+        }
+
+        let c = text.get(s).copied();
+        let result = match self.tokens.get(t) {
+            None => c.is_none(),
+            Some(Token::Char(expected)) => {
+                c == Some(*expected) && self.match_from(t + 1, s + 1, text, memo)
+            }
+            Some(Token::AnyChar) => {
+                c.is_some_and(|c| c != '/') && self.match_from(t + 1, s + 1, text, memo)
+            }
+            Some(Token::Class { ranges, negated }) => {
+                c.is_some_and(|c| {
+                    let found = ranges.iter().any(|&(low, high)| low <= c && c <= high);
+                    c != '/' && found != *negated
+                }) && self.match_from(t + 1, s + 1, text, memo)
+            }
+            Some(Token::Star) => {
+                self.match_from(t + 1, s, text, memo)
+                    || (c.is_some_and(|c| c != '/') && self.match_from(t, s + 1, text, memo))
+            }
+            Some(Token::DoubleStar) => {
+                self.match_from(t + 1, s, text, memo)
+                    || (c.is_some() && self.match_from(t, s + 1, text, memo))
+            }
+            Some(Token::Dirs) => {
+                // skip nothing, or skip up to and including any later `/`
+                self.match_from(t + 1, s, text, memo)
+                    || (s..text.len())
+                        .any(|i| text[i] == '/' && self.match_from(t + 1, i + 1, text, memo))
+            }
+        };
+
+        memo[key] = Some(result);
+        result
+    }
+}
+
+// Reads a class from just after its `[`, returns it and how many chars it used including the `]`.
+// A `]` right at the start is part of the class, ex. `[]a]`.
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    let mut i = usize::from(negated);
+    let mut ranges = vec![];
+
+    loop {
+        let mut low = *chars.get(i)?;
+        if low == ']' && !ranges.is_empty() {
+            return Some((Token::Class { ranges, negated }, i + 1));
+        }
+        if low == '\\' {
+            i += 1;
+            low = *chars.get(i)?;
+        }
+        // `-` is a range between two chars, or itself at either end
+        let high = match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('-'), Some(&high)) if high != ']' => {
+                i += 2;
+                high
+            }
+            _ => low,
+        };
+        ranges.push((low, high));
+        i += 1;
+    }
+}
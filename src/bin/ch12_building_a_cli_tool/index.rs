@@ -0,0 +1,483 @@
+// A trigram index that lets repeated searches skip files that can't match.
+// - `minigrep --build-index DIR` lists every 3 byte sequence (trigram) of the case folded text
+//   of each file in DIR, and writes which files contain each trigram to `DIR/.minigrep-index`.
+//   Running it again only reads the files whose size or modified time changed.
+// - `minigrep --index QUERY DIR` looks up the trigrams of the query, and only searches the files
+//   that have all of them. Files that are new or changed since the index was built are always
+//   searched, so an old index makes searches slower but never wrong.
+// ex. "frog" needs "fro" and "rog", so only files listed under both are read
+use super::archive;
+use super::encoding;
+use super::lib::{Config, EXIT_ERROR, EXIT_MATCH};
+use super::printer::OutputMode;
+use super::query::Query;
+use super::replace;
+use super::unicode;
+use super::walk::{self, Filter};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Where the index of a directory is kept. It's hidden, so it's never searched itself.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep index 1\n";
+
+/// Runs `minigrep --build-index DIR` and returns the exit code
+pub fn command(dir: &str) -> i32 {
+    match build(Path::new(dir)) {
+        Ok(stats) => {
+            println!(
+                "indexed {} files in {} ({} read, {} unchanged)",
+                stats.files,
+                dir,
+                stats.read,
+                stats.files - stats.read
+            );
+            if stats.skipped > 0 {
+                EXIT_ERROR
+            } else {
+                EXIT_MATCH
+            }
+        }
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// What building an index did
+#[derive(Debug, PartialEq)]
+pub struct BuildStats {
+    pub files: usize,
+    // files that were new or changed, the rest were taken from the last index
+    pub read: usize,
+    // files and folders that couldn't be read, which are left out so every search reads them
+    pub skipped: usize,
+}
+
+/// Builds or updates the index of a directory
+pub fn build(dir: &Path) -> io::Result<BuildStats> {
+    let dir_name = dir.to_string_lossy();
+    let walked = walk::files(&[dir_name.as_ref()], &Filter::default());
+    walked.report_errors();
+    // an unreadable old index is rebuilt from scratch
+    let old = Index::open(dir).ok();
+    let old_trigrams = old
+        .as_ref()
+        .map(Index::trigrams_by_file)
+        .unwrap_or_default();
+
+    let mut indexed = vec![];
+    let mut stats = BuildStats {
+        files: 0,
+        read: 0,
+        // the files of a folder that can't be read aren't known, so the folder counts as one
+        skipped: walked.errors.len(),
+    };
+
+    for file in walked.files {
+        let relative = match file.strip_prefix(dir).ok().and_then(Path::to_str) {
+            Some(relative) => relative.replace('\\', "/"),
+            // paths that aren't UTF-8 are left out, and always searched
+            None => continue,
+        };
+        // ex. a file that was deleted since the folder was listed
+        let stamp = match Stamp::of(&file) {
+            Ok(stamp) => stamp,
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        let unchanged = old.as_ref().and_then(|old| {
+            let id = old.find(&relative, stamp)?;
+            Some((old.files[id].searched_whole, old_trigrams.get(&(id as u32))))
+        });
+        let (searched_whole, trigrams) = match unchanged {
+            Some((searched_whole, trigrams)) => {
+                (searched_whole, trigrams.cloned().unwrap_or_default())
+            }
+            None => {
+                let contents = match fs::read(&file) {
+                    Ok(contents) => contents,
+                    Err(e) => {
+                        eprintln!("{}: {}", file.display(), e);
+                        stats.skipped += 1;
+                        continue;
+                    }
+                };
+                stats.read += 1;
+                // the trigrams of UTF-16 text and archives aren't the ones of what's searched
+                if contents.starts_with(&[0xff, 0xfe])
+                    || contents.starts_with(&[0xfe, 0xff])
+                    || archive::format(&contents).is_some()
+                {
+                    (true, vec![])
+                } else {
+                    (false, trigrams(&contents))
+                }
+            }
+        };
+
+        indexed.push((
+            IndexedFile {
+                path: relative,
+                stamp,
+                searched_whole,
+            },
+            trigrams,
+        ));
+        stats.files += 1;
+    }
+
+    // files are looked up by path, so their ids go in path order
+    indexed.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+    let mut index = Index::default();
+    for (file, trigrams) in indexed {
+        let id = index.files.len() as u32;
+        for trigram in trigrams {
+            index.postings.entry(trigram).or_default().push(id);
+        }
+        index.files.push(file);
+    }
+
+    replace::write_atomically(&dir.join(INDEX_FILE), &index.encode(), None)?;
+    Ok(stats)
+}
+
+/// Narrows the files of a search down to the ones the indexes of its directories say can match
+pub fn narrow(config: &Config, files: Vec<PathBuf>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let plan = plan(config)?;
+    let mut indexes = vec![];
+    for path in &config.paths {
+        let dir = Path::new(path);
+        if !dir.is_dir() {
+            continue;
+        }
+        let index = Index::open(dir).map_err(|e| {
+            format!(
+                "can't read the index of {}: {}, run `minigrep --build-index {}` first",
+                path, e, path
+            )
+        })?;
+        let candidates = index.candidates(&plan);
+        indexes.push((dir, index, candidates));
+    }
+
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            let (dir, index, candidates) =
+                match indexes.iter().find(|(dir, _, _)| file.starts_with(dir)) {
+                    Some(found) => found,
+                    None => return true,
+                };
+            let id = file
+                .strip_prefix(dir)
+                .ok()
+                .and_then(Path::to_str)
+                .zip(Stamp::of(file).ok())
+                .and_then(|(relative, stamp)| index.find(&relative.replace('\\', "/"), stamp));
+
+            match (id, candidates) {
+                (Some(id), Some(candidates)) => {
+                    index.files[id].searched_whole || candidates.contains(&(id as u32))
+                }
+                // files the index doesn't know about, or every file
+                _ => true,
+            }
+        })
+        .collect())
+}
+
+/// Which trigrams a file needs to have to possibly match
+#[derive(Debug, PartialEq)]
+pub enum Plan {
+    // the query doesn't say, so every file is searched
+    All,
+    Trigrams(Vec<u32>),
+    And(Vec<Plan>),
+    Or(Vec<Plan>),
+}
+
+/// Works out which trigrams a search needs
+pub fn plan(config: &Config) -> Result<Plan, Box<dyn Error>> {
+    // these can select lines without the query in them, or compare other text than the file's
+    let anything = config.invert
+        || config.mode == OutputMode::FilesWithoutMatches
+        || config.regex
+        || config.normalize.is_some()
+        || config.encoding.is_some();
+    if anything {
+        return Ok(Plan::All);
+    }
+
+    if config.boolean {
+        let query = Query::new(&config.patterns[0], !config.case_sensitive, config.boundary)?;
+        return Ok(query.plan(&literal));
+    }
+
+    let mut plans: Vec<Plan> = config
+        .patterns
+        .iter()
+        .map(|pattern| match config.fuzzy {
+            Some(max_distance) => fuzzy(pattern, max_distance),
+            // the words have to be there, whatever is between them
+            None if config.flex_space => {
+                Plan::And(pattern.split_whitespace().map(literal).collect())
+            }
+            None => literal(pattern),
+        })
+        .collect();
+    if plans.len() == 1 {
+        Ok(plans.remove(0))
+    } else {
+        Ok(Plan::Or(plans))
+    }
+}
+
+/// The trigrams that text with this literal in it has. Case folding keeps a substring a
+/// substring, so this is the same with and without ignoring case.
+pub fn literal(text: &str) -> Plan {
+    let trigrams = trigrams(text.as_bytes());
+    if trigrams.is_empty() {
+        Plan::All
+    } else {
+        Plan::Trigrams(trigrams)
+    }
+}
+
+// Split into one more piece than the allowed edits, a fuzzy match has at least one piece unchanged.
+// ex. "frog" with 1 edit has "fr" or "og", which are too short to need any trigram
+fn fuzzy(text: &str, max_distance: usize) -> Plan {
+    let chars: Vec<char> = text.chars().collect();
+    let pieces = max_distance + 1;
+    let plans = (0..pieces)
+        .map(|i| {
+            let piece: String = chars[i * chars.len() / pieces..(i + 1) * chars.len() / pieces]
+                .iter()
+                .collect();
+            literal(&piece)
+        })
+        .collect();
+    Plan::Or(plans)
+}
+
+// the sorted trigrams of the case folded text, packed into the low 3 bytes of a u32
+fn trigrams(bytes: &[u8]) -> Vec<u32> {
+    let text = encoding::lossy_line(bytes);
+    let mut folded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            folded.push(c.to_ascii_lowercase() as u8);
+        } else {
+            for c in unicode::fold(c) {
+                folded.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+        }
+    }
+
+    let mut trigrams: Vec<u32> = folded
+        .windows(3)
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+/// The size and modified time of a file, which change when it's written to
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> io::Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(Stamp {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct IndexedFile {
+    // relative to the indexed directory, with `/` between folders
+    path: String,
+    stamp: Stamp,
+    // set for files whose trigrams aren't known, which every search has to read
+    searched_whole: bool,
+}
+
+/// The files of a directory, and the files each trigram is in
+#[derive(Debug, Default)]
+struct Index {
+    files: Vec<IndexedFile>,
+    // the ids of the files with each trigram, in increasing order
+    postings: HashMap<u32, Vec<u32>>,
+}
+
+impl Index {
+    fn open(dir: &Path) -> io::Result<Index> {
+        let bytes = fs::read(dir.join(INDEX_FILE))?;
+        Index::decode(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the index is damaged"))
+    }
+
+    // the id of a file, if it hasn't changed since it was indexed
+    fn find(&self, path: &str, stamp: Stamp) -> Option<usize> {
+        let id = self
+            .files
+            .binary_search_by(|file| file.path.as_str().cmp(path))
+            .ok()?;
+        Some(id).filter(|&id| self.files[id].stamp == stamp)
+    }
+
+    fn trigrams_by_file(&self) -> HashMap<u32, Vec<u32>> {
+        let mut by_file: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (&trigram, ids) in &self.postings {
+            for &id in ids {
+                by_file.entry(id).or_default().push(trigram);
+            }
+        }
+        by_file
+    }
+
+    // the ids of the files that can match, or `None` when they all can
+    fn candidates(&self, plan: &Plan) -> Option<HashSet<u32>> {
+        match plan {
+            Plan::All => None,
+            Plan::Trigrams(trigrams) => {
+                let mut lists: Vec<&[u32]> = trigrams
+                    .iter()
+                    .map(|trigram| self.postings.get(trigram).map_or(&[][..], Vec::as_slice))
+                    .collect();
+                // start from the rarest trigram, so there's less to check
+                lists.sort_by_key(|list| list.len());
+                let mut ids: HashSet<u32> = lists[0].iter().copied().collect();
+                for list in &lists[1..] {
+                    ids.retain(|id| list.binary_search(id).is_ok());
+                }
+                Some(ids)
+            }
+            Plan::And(plans) => plans
+                .iter()
+                .filter_map(|plan| self.candidates(plan))
+                .reduce(|all, ids| all.intersection(&ids).copied().collect()),
+            Plan::Or(plans) => {
+                let mut all = HashSet::new();
+                for plan in plans {
+                    all.extend(self.candidates(plan)?);
+                }
+                Some(all)
+            }
+        }
+    }
+
+    // Files are listed by path, then each trigram has a list of file ids.
+    // Ids only go up, so they're stored as the difference from the last one, in as few bytes
+    // as they fit in (7 bits per byte, the high bit says another byte follows).
+    fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        write_number(&mut out, self.files.len() as u64);
+        for file in &self.files {
+            write_number(&mut out, file.path.len() as u64);
+            out.extend_from_slice(file.path.as_bytes());
+            write_number(&mut out, file.stamp.size);
+            write_number(&mut out, file.stamp.modified_secs);
+            write_number(&mut out, file.stamp.modified_nanos as u64);
+            out.push(file.searched_whole as u8);
+        }
+
+        let mut trigrams: Vec<&u32> = self.postings.keys().collect();
+        trigrams.sort();
+        write_number(&mut out, trigrams.len() as u64);
+        for trigram in trigrams {
+            let ids = &self.postings[trigram];
+            write_number(&mut out, *trigram as u64);
+            write_number(&mut out, ids.len() as u64);
+            let mut last = 0;
+            for &id in ids {
+                write_number(&mut out, (id - last) as u64);
+                last = id;
+            }
+        }
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Index> {
+        let mut input = bytes.strip_prefix(MAGIC)?;
+        let mut index = Index::default();
+
+        for _ in 0..read_number(&mut input)? {
+            let len = read_number(&mut input)? as usize;
+            let path = std::str::from_utf8(input.get(..len)?).ok()?.to_string();
+            input = &input[len..];
+            let stamp = Stamp {
+                size: read_number(&mut input)?,
+                modified_secs: read_number(&mut input)?,
+                modified_nanos: read_number(&mut input)? as u32,
+            };
+            let (&searched_whole, rest) = input.split_first()?;
+            input = rest;
+            index.files.push(IndexedFile {
+                path,
+                stamp,
+                searched_whole: searched_whole != 0,
+            });
+        }
+
+        for _ in 0..read_number(&mut input)? {
+            let trigram = read_number(&mut input)? as u32;
+            let mut ids = vec![];
+            let mut last = 0;
+            for _ in 0..read_number(&mut input)? {
+                // a damaged index can have ids past the largest one
+                let step = u32::try_from(read_number(&mut input)?).ok()?;
+                last = u32::checked_add(last, step)?;
+                ids.push(last);
+            }
+            index.postings.insert(trigram, ids);
+        }
+
+        Some(index).filter(|_| input.is_empty())
+    }
+}
+
+fn write_number(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_number(input: &mut &[u8]) -> Option<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first()?;
+        *input = rest;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Some(n);
+        }
+    }
+    None
+}
@@ -0,0 +1,540 @@
+// A full screen browser for the matches, for when there are too many to scroll through.
+// The screen has the query at the top, the matching lines below it, and a preview of the lines
+// around the selected match. Each edit of the query searches again.
+// - ↑/↓ (or Ctrl-P/Ctrl-N) and PgUp/PgDn move the selection
+// - Enter opens the selected line in $VISUAL or $EDITOR, ex. `vi +7 poem.txt`
+// - Esc or Ctrl-C quits
+// It's drawn with ANSI escape codes, and `stty` switches the terminal to reading single keys.
+// ex. $ cargo run --bin ch12_building_a_cli_tool -- --interactive -i frog ./assets
+use super::encoding;
+use super::lib::{Config, Hit, Searcher, EXIT_MATCH, EXIT_NO_MATCH};
+use super::unicode;
+use super::walk;
+use std::env;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// stops a query that matches every line of a big folder from taking too long
+const MAX_RESULTS: usize = 10_000;
+
+// ANSI escape codes. Matches only turn off their own attributes, so they can be inside a
+// selected row.
+const MATCH_ON: &str = "\x1b[1;31m";
+const MATCH_OFF: &str = "\x1b[22;39m";
+const SELECTED: &str = "\x1b[7m";
+const DIM: &str = "\x1b[2m";
+const ERROR: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+const CLEAR_LINE: &str = "\x1b[K";
+
+/// A key that was pressed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Enter,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Escape,
+}
+
+/// Reads the keys in what the terminal sent. A lone escape byte is the Esc key,
+/// since the escape sequences of other keys arrive all at once.
+pub fn keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x1b => {
+                let rest = &bytes[i + 1..];
+                let (key, len) = match rest {
+                    [b'[', b'A', ..] | [b'O', b'A', ..] => (Some(Key::Up), 2),
+                    [b'[', b'B', ..] | [b'O', b'B', ..] => (Some(Key::Down), 2),
+                    [b'[', b'5', b'~', ..] => (Some(Key::PageUp), 3),
+                    [b'[', b'6', b'~', ..] => (Some(Key::PageDown), 3),
+                    // skip any other sequence, up to its final byte
+                    [b'[', ..] => {
+                        let end = rest[1..].iter().position(|b| (0x40..=0x7e).contains(b));
+                        (None, end.map_or(rest.len(), |end| end + 2))
+                    }
+                    _ => (Some(Key::Escape), 0),
+                };
+                keys.extend(key);
+                i += 1 + len;
+            }
+            // Ctrl-C, which raw mode doesn't turn into a signal
+            0x03 => {
+                keys.push(Key::Escape);
+                i += 1;
+            }
+            b'\r' | b'\n' => {
+                keys.push(Key::Enter);
+                i += 1;
+            }
+            0x7f | 0x08 => {
+                keys.push(Key::Backspace);
+                i += 1;
+            }
+            0x0e => {
+                keys.push(Key::Down);
+                i += 1;
+            }
+            0x10 => {
+                keys.push(Key::Up);
+                i += 1;
+            }
+            b if b < 0x20 => i += 1,
+            _ => {
+                let len = bytes[i..]
+                    .iter()
+                    .position(|&b| b < 0x20 || b == 0x7f)
+                    .unwrap_or(bytes.len() - i);
+                let text = String::from_utf8_lossy(&bytes[i..i + len]);
+                keys.extend(text.chars().map(Key::Char));
+                i += len;
+            }
+        }
+    }
+    keys
+}
+
+/// A matching line, by its place in `Browser.files`
+#[derive(Debug, PartialEq)]
+pub struct Found {
+    pub file: usize,
+    // counted from 0
+    pub line: usize,
+    pub hits: Vec<Hit>,
+}
+
+/// What the program has to do after a key press
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Nothing,
+    // a path and a line number counted from 1
+    Open(String, usize),
+    Quit,
+}
+
+/// The state of the screen: the query, what it found, and which match is selected
+pub struct Browser {
+    pub query: String,
+    // each path with its lines, read once so searching again doesn't touch the disk
+    files: Vec<(String, Vec<String>)>,
+    pub found: Vec<Found>,
+    pub selected: usize,
+    // the first match shown in the list
+    top: usize,
+    // why the last query couldn't be searched, ex. an unfinished regex, or the editor failed
+    error: Option<String>,
+}
+
+impl Browser {
+    pub fn new(files: Vec<(String, Vec<String>)>, query: &str) -> Browser {
+        Browser {
+            query: query.to_string(),
+            files,
+            found: vec![],
+            selected: 0,
+            top: 0,
+            error: None,
+        }
+    }
+
+    /// Searches every line for the query. An invalid query keeps the matches of the last one.
+    pub fn search(&mut self, config: &mut Config) {
+        config.patterns = vec![self.query.clone()];
+        let searcher = match Searcher::new(config) {
+            Ok(searcher) => searcher,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+
+        self.error = None;
+        self.found.clear();
+        'files: for (file, (_, lines)) in self.files.iter().enumerate() {
+            for (line, text) in lines.iter().enumerate() {
+                let hits = searcher.hits(text);
+                if hits.is_empty() == searcher.invert {
+                    if self.found.len() == MAX_RESULTS {
+                        break 'files;
+                    }
+                    self.found.push(Found { file, line, hits });
+                }
+            }
+        }
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    pub fn press(&mut self, key: Key, page: usize, config: &mut Config) -> Action {
+        let last = self.found.len().saturating_sub(1);
+        match key {
+            Key::Char(c) => {
+                self.query.push(c);
+                self.search(config);
+            }
+            Key::Backspace => {
+                if self.query.pop().is_some() {
+                    self.search(config);
+                }
+            }
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(page),
+            Key::PageDown => self.selected = (self.selected + page).min(last),
+            Key::Enter => {
+                if let Some(found) = self.found.get(self.selected) {
+                    return Action::Open(self.files[found.file].0.clone(), found.line + 1);
+                }
+            }
+            Key::Escape => return Action::Quit,
+        }
+        Action::Nothing
+    }
+
+    /// Draws the whole screen. The list gets the top half, and the preview the rest.
+    pub fn draw<W: Write>(&mut self, out: &mut W, rows: usize, cols: usize) -> io::Result<()> {
+        let rows = rows.max(5);
+        let list_rows = (rows - 3) / 2;
+        let preview_rows = rows - 3 - list_rows;
+
+        // keep the selection on screen
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + list_rows {
+            self.top = self.selected + 1 - list_rows;
+        }
+
+        write!(
+            out,
+            "\x1b[?25l\x1b[H> {}",
+            fit(&self.query, cols.saturating_sub(2))
+        )?;
+        let status = match &self.error {
+            Some(error) => format!("  {}{}{}", ERROR, fit(error, cols / 2), RESET),
+            None if self.found.len() == MAX_RESULTS => {
+                format!("  {}{}+ matches{}", DIM, MAX_RESULTS, RESET)
+            }
+            None if self.found.len() == 1 => format!("  {}1 match{}", DIM, RESET),
+            None => format!("  {}{} matches{}", DIM, self.found.len(), RESET),
+        };
+        write!(out, "{}{}\r\n", status, CLEAR_LINE)?;
+
+        for row in 0..list_rows {
+            if let Some(found) = self.found.get(self.top + row) {
+                let (path, lines) = &self.files[found.file];
+                let prefix = format!("{}:{}:", path, found.line + 1);
+                let selected = self.top + row == self.selected;
+                write_line(
+                    out,
+                    &prefix,
+                    &lines[found.line],
+                    &found.hits,
+                    cols,
+                    selected,
+                )?;
+            }
+            write!(out, "{}\r\n", CLEAR_LINE)?;
+        }
+
+        let found = self.found.get(self.selected);
+        let title = found.map_or("", |found| self.files[found.file].0.as_str());
+        let title = fit(title, cols.saturating_sub(4));
+        let rule = "─".repeat(cols.saturating_sub(unicode::str_width(&title) + 4));
+        write!(
+            out,
+            "{}── {} {}{}{}\r\n",
+            DIM, title, rule, RESET, CLEAR_LINE
+        )?;
+
+        // the lines around the match, with the match in the middle
+        for row in 0..preview_rows {
+            if let Some(found) = found {
+                let lines = &self.files[found.file].1;
+                let line = (found.line + row).checked_sub(preview_rows / 2);
+                if let Some((line, text)) = line.and_then(|line| Some((line, lines.get(line)?))) {
+                    let (hits, selected) = if line == found.line {
+                        (found.hits.as_slice(), true)
+                    } else {
+                        (&[][..], false)
+                    };
+                    let prefix = format!("{:>6} ", line + 1);
+                    write_line(out, &prefix, text, hits, cols, selected)?;
+                }
+            }
+            write!(out, "{}\r\n", CLEAR_LINE)?;
+        }
+
+        let help = "↑/↓ move  PgUp/PgDn page  Enter open in $EDITOR  Esc quit";
+        write!(out, "{}{}{}{}", DIM, fit(help, cols), RESET, CLEAR_LINE)?;
+        // leave the cursor at the end of the query
+        let column = (unicode::str_width(&self.query) + 3).min(cols);
+        write!(out, "\x1b[1;{}H\x1b[?25h", column)?;
+        out.flush()
+    }
+}
+
+// Cuts text down to a width in columns, and swaps tabs and other control chars for spaces so
+// they can't move the cursor
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    for c in text.chars().map(printable) {
+        used += unicode::width(c);
+        if used > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    fitted
+}
+
+fn printable(c: char) -> char {
+    if c.is_control() {
+        ' '
+    } else {
+        c
+    }
+}
+
+// writes a row with its matches highlighted, the selected row is shown in reverse
+fn write_line<W: Write>(
+    out: &mut W,
+    prefix: &str,
+    text: &str,
+    hits: &[Hit],
+    cols: usize,
+    selected: bool,
+) -> io::Result<()> {
+    if selected {
+        out.write_all(SELECTED.as_bytes())?;
+    }
+    let prefix = fit(prefix, cols);
+    write!(out, "{}", prefix)?;
+
+    let mut width = unicode::str_width(&prefix);
+    for (i, c) in text.char_indices() {
+        let shown = printable(c);
+        // a wide char that doesn't fit is left off rather than split across lines
+        if width + unicode::width(shown) > cols {
+            break;
+        }
+        if hits
+            .iter()
+            .any(|hit| hit.bytes.start == i && !hit.bytes.is_empty())
+        {
+            out.write_all(MATCH_ON.as_bytes())?;
+        }
+        write!(out, "{}", shown)?;
+        if hits.iter().any(|hit| hit.bytes.end == i + c.len_utf8()) {
+            out.write_all(MATCH_OFF.as_bytes())?;
+        }
+        width += unicode::width(shown);
+    }
+
+    // a selected row is highlighted all the way across
+    if selected {
+        write!(out, "{}{}", " ".repeat(cols - width), RESET)?;
+    }
+    Ok(())
+}
+
+/// Reads every file into memory, then runs the browser until it's quit.
+/// Returns 0 when the last query matched something, like a search would.
+pub fn run(mut config: Config) -> Result<i32, Box<dyn Error>> {
+    let mut files = vec![];
+    let walked = walk::files(&config.paths, &config.filter);
+    walked.report_errors();
+    for file in walked.files {
+        match read_lines(&file, &config) {
+            Ok(Some(lines)) => files.push((file.display().to_string(), lines)),
+            // binary files are skipped
+            Ok(None) => {}
+            Err(e) => eprintln!("{}: {}", file.display(), e),
+        }
+    }
+
+    let mut browser = Browser::new(files, &config.patterns[0]);
+    browser.search(&mut config);
+    let mut terminal = Terminal::open()?;
+    let mut buffer = [0; 64];
+    'screen: loop {
+        let (rows, cols) = terminal.size();
+        // one write per frame, so the screen doesn't flicker
+        let mut frame = vec![];
+        browser.draw(&mut frame, rows, cols)?;
+        terminal.tty.write_all(&frame)?;
+
+        let read = match terminal.tty.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            // a resize interrupts the read, so the screen is drawn again at the new size
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for key in keys(&buffer[..read]) {
+            match browser.press(key, rows / 2, &mut config) {
+                Action::Nothing => {}
+                Action::Open(path, line_number) => {
+                    terminal.suspend()?;
+                    let opened = open_in_editor(&path, line_number, &terminal.tty);
+                    terminal.resume()?;
+                    if let Err(e) = opened {
+                        browser.error = Some(e.to_string());
+                    }
+                }
+                Action::Quit => break 'screen,
+            }
+        }
+    }
+
+    Ok(if browser.found.is_empty() {
+        EXIT_NO_MATCH
+    } else {
+        EXIT_MATCH
+    })
+}
+
+// the lines of a file, or `None` for a binary file
+fn read_lines(file: &Path, config: &Config) -> io::Result<Option<Vec<String>>> {
+    let mut reader = encoding::decode(BufReader::new(File::open(file)?), config.encoding)?;
+    let mut contents = vec![];
+    reader.read_to_end(&mut contents)?;
+    if contents.contains(&0) {
+        return Ok(None);
+    }
+    let text = encoding::lossy_line(&contents);
+    Ok(Some(text.lines().map(String::from).collect()))
+}
+
+// ex. `vi +7 poem.txt`, most editors read `+N` as the line to start on
+fn open_in_editor(path: &str, line_number: usize, tty: &File) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // the editor can come with its own args, ex. `emacs -nw`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line_number))
+        .arg(path)
+        .stdin(tty.try_clone()?)
+        .stdout(tty.try_clone()?)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} exited with {}",
+            program, status
+        )))
+    }
+}
+
+// The terminal the browser is drawn on, which is put back the way it was when dropped.
+// It's opened directly so the browser works even when the output is piped.
+struct Terminal {
+    tty: File,
+    // the settings from `stty -g`
+    saved: String,
+    // rows and columns, read again only after a resize
+    size: (usize, usize),
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let saved = stty(&tty, &["-g"])?;
+        watch_resizes();
+        let mut terminal = Terminal {
+            size: read_size(&tty),
+            tty,
+            saved: saved.trim().to_string(),
+        };
+        terminal.resume()?;
+        Ok(terminal)
+    }
+
+    fn size(&mut self) -> (usize, usize) {
+        if RESIZED.swap(false, Ordering::Relaxed) {
+            self.size = read_size(&self.tty);
+        }
+        self.size
+    }
+
+    // switches to a screen of its own, reading each key as it's pressed
+    fn resume(&mut self) -> io::Result<()> {
+        stty(&self.tty, &["raw", "-echo"])?;
+        self.tty.write_all(b"\x1b[?1049h\x1b[2J")
+    }
+
+    // goes back to the normal screen and settings, for the editor or when quitting
+    fn suspend(&mut self) -> io::Result<()> {
+        self.tty.write_all(b"\x1b[?25h\x1b[?1049l")?;
+        stty(&self.tty, &[&self.saved]).map(|_| ())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+// rows and columns, 24x80 when the terminal doesn't say
+fn read_size(tty: &File) -> (usize, usize) {
+    let size = stty(tty, &["size"]).unwrap_or_default();
+    let mut numbers = size.split_whitespace().filter_map(|n| n.parse().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(rows), Some(cols)) if rows > 0 && cols > 0 => (rows, cols),
+        _ => (24, 80),
+    }
+}
+
+// set when the terminal is resized, so the size is only asked for again when it changed
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+// Catches SIGWINCH, which the terminal sends when it's resized. The handler is made to
+// interrupt a read that's waiting for a key, so the new size shows straight away.
+#[cfg(unix)]
+fn watch_resizes() {
+    // SIGWINCH is 28 on Linux, macOS and the BSDs
+    const SIGWINCH: i32 = 28;
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn siginterrupt(signum: i32, flag: i32) -> i32;
+    }
+    extern "C" fn resized(_: i32) {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+    // only an atomic store happens in the handler, which is safe to do in a signal handler
+    unsafe {
+        signal(SIGWINCH, resized);
+        siginterrupt(SIGWINCH, 1);
+    }
+}
+
+#[cfg(not(unix))]
+fn watch_resizes() {}
+
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone()?)
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty couldn't change the terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,74 +1,1052 @@
+use super::aho_corasick::AhoCorasick;
+use super::archive::{self, Format};
+use super::args::{self, ArgsError};
+use super::encoding::{self, Encoding};
+use super::fuzzy::Fuzzy;
+use super::index;
+use super::printer::{ColorChoice, OutputMode, Printer};
+use super::query::Query;
+use super::rank;
+use super::records::{Records, Separator};
+use super::regex::{self, Regex};
+use super::replace::{self, Edit};
+use super::substring::Finder;
+use super::unicode::{self, Form, Normalized};
+use super::walk::{self, Filter};
+use super::watch::Watch;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    // read file text
-    let contents = fs::read_to_string(config.filename)?;
+/// Exit codes, the same as grep's
+pub const EXIT_MATCH: i32 = 0;
+pub const EXIT_NO_MATCH: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
 
-    // search with/without case sensitivity
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
-    } else {
-        search_case_insensitive(&config.query, &contents)
-    };
+/// Searches every path and returns the exit code
+pub fn run(config: &Config) -> Result<i32, Box<dyn Error>> {
+    // expand folders into the files inside of them
+    let walked = walk::files(&config.paths, &config.filter);
+    let walk_failed = walked.report_errors();
+    let mut files = walked.files;
+    if config.index {
+        files = index::narrow(config, files)?;
+    }
+    if let Some(top) = config.rank {
+        let code = rank::run(config, &files, top)?;
+        return Ok(if walk_failed { EXIT_ERROR } else { code });
+    }
+    let with_filename = config.with_filename();
+    let searcher = Searcher::new(config)?;
+
+    // one thread per core, but never more than there are files
+    let threads = config
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, files.len().max(1));
+
+    let started = Instant::now();
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), config, with_filename);
+    let mut selected_any = false;
+    let mut had_error = walk_failed;
+
+    // This thread writes the files in order. The file whose turn it is is searched here,
+    // straight into the output, unless a worker already took it. Workers take the files after it
+    // and send back what they printed, which waits here until the files before it are written.
+    // So one file, or -j1, streams its lines as they're found.
+    let claimed: Vec<AtomicBool> = files.iter().map(|_| AtomicBool::new(false)).collect();
+    let next_file = AtomicUsize::new(1);
+    let (sender, receiver) = mpsc::channel();
+    let quit_early = thread::scope(|scope| -> io::Result<bool> {
+        for _ in 1..threads {
+            let sender = sender.clone();
+            let (files, claimed, next_file, searcher) = (&files, &claimed, &next_file, &searcher);
+            scope.spawn(move || loop {
+                let index = next_file.fetch_add(1, Ordering::Relaxed);
+                let file = match files.get(index) {
+                    Some(file) => file,
+                    None => break,
+                };
+                if claimed[index].swap(true, Ordering::Relaxed) {
+                    continue;
+                }
+
+                let mut file_printer = Printer::new(vec![], config, with_filename);
+                let result = searcher.search_file(file, &mut file_printer);
+                // the receiver is gone once the search has stopped early
+                if sender.send((index, file_printer, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
 
-    // print each search match
-    for line in results {
-        println!("{}", line);
+        let mut waiting = HashMap::new();
+        for (index, file) in files.iter().enumerate() {
+            let result = if !claimed[index].swap(true, Ordering::Relaxed) {
+                searcher.search_file(file, &mut printer)
+            } else {
+                let (file_printer, result) = loop {
+                    if let Some(done) = waiting.remove(&index) {
+                        break done;
+                    }
+                    match receiver.recv() {
+                        Ok((i, file_printer, result)) => waiting.insert(i, (file_printer, result)),
+                        Err(_) => return Err(io::Error::other("a search thread stopped")),
+                    };
+                };
+                match printer.append(file_printer) {
+                    Ok(()) => result,
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Err(e),
+                    Err(e) => return Err(e),
+                }
+            };
+
+            let count = match result {
+                Ok(count) => count,
+                // whatever reads the output stopped, ex. `minigrep frog big.txt | head`,
+                // so lines were selected and nothing more can be written
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                    next_file.store(files.len(), Ordering::Relaxed);
+                    return Ok(true);
+                }
+                // report files that can't be read, then keep going
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    had_error = true;
+                    continue;
+                }
+            };
+
+            // -L succeeds when it lists a file
+            selected_any |= if config.mode == OutputMode::FilesWithoutMatches {
+                count == 0
+            } else {
+                count > 0
+            };
+
+            // -q only needs to know that something matched, so the other files are skipped,
+            // unless they still have to be edited
+            if selected_any && config.mode == OutputMode::Quiet && config.edit.is_none() {
+                next_file.store(files.len(), Ordering::Relaxed);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })?;
+
+    if quit_early {
+        return Ok(EXIT_MATCH);
+    }
+    match printer.finish(started.elapsed()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(EXIT_MATCH),
+        finished => finished?,
     }
 
-    Ok(())
+    if had_error {
+        Ok(EXIT_ERROR)
+    } else if selected_any {
+        Ok(EXIT_MATCH)
+    } else {
+        Ok(EXIT_NO_MATCH)
+    }
 }
 
+/// The path used for standard input
+pub const STDIN: &str = "-";
+
 /// User args for grep-like command
 pub struct Config<'a> {
-    pub query: &'a str,
-    pub filename: &'a str,
+    // the query, or every -e/-f pattern
+    pub patterns: Vec<String>,
+    // files and folders to search
+    pub paths: Vec<&'a str>,
+    // which files inside the folders to search, from --glob, --type and --type-not
+    pub filter: Filter,
     pub case_sensitive: bool,
+    pub regex: bool,
+    // parse the query as terms combined with AND, OR and NOT
+    pub boolean: bool,
+    // only match whole words or lines
+    pub boundary: Boundary,
+    // match against whole files, so a match can span lines
+    pub multiline: bool,
+    // let any run of whitespace in the query match any other, including newlines
+    pub flex_space: bool,
+    // match text up to this many edits away from the patterns
+    pub fuzzy: Option<usize>,
+    // print this many files or records, best scoring first, instead of every matching line
+    pub rank: Option<usize>,
+    // select the lines that don't match
+    pub invert: bool,
+    pub mode: OutputMode,
+    // stop reading a file after this many selected lines
+    pub max_count: Option<usize>,
+    // show where each match is
+    pub line_number: bool,
+    pub column: bool,
+    pub color: ColorChoice,
+    // lines to show around each match
+    pub before_context: usize,
+    pub after_context: usize,
+    // read files as this encoding instead of UTF-8, unless they have a BOM
+    pub encoding: Option<Encoding>,
+    // how many files to search at once, defaults to one per core
+    pub threads: Option<usize>,
+    // only search the files the index of each folder says can match
+    pub index: bool,
+    // write the index of this folder instead of searching
+    pub build_index: Option<&'a str>,
+    // compare text after writing its accents the same way
+    pub normalize: Option<Form>,
+    // what splits the input into the records that are searched
+    pub separator: Separator,
+    // text to print, or write, in place of each match
+    pub replace: Option<String>,
+    // write the replacements back to the files, or show what would change
+    pub edit: Option<Edit>,
+    // keep searching as the files change
+    pub watch: Option<Watch>,
+    // browse the matches on a full screen, refining the query while typing
+    pub interactive: bool,
 }
 
 impl<'a> Config<'a> {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
-        }
-
-        Ok(Config {
-            query: &args[1],
-            filename: &args[2],
-            // set based on if environment variable is present.
+    pub fn new(args: &'a [String]) -> Result<Config<'a>, ArgsError> {
+        let mut config = Config {
+            patterns: vec![],
+            paths: vec![],
+            filter: Filter::default(),
+            // set based on if environment variable is present, flags can override it.
             // ex. $ CASE_INSENSITIVE=1 cargo run --bin ch12_building_a_cli_tool to ./assets/poem.txt
             case_sensitive: env::var("CASE_INSENSITIVE").is_err(),
+            regex: false,
+            boolean: false,
+            boundary: Boundary::Anywhere,
+            multiline: false,
+            flex_space: false,
+            fuzzy: None,
+            rank: None,
+            invert: false,
+            mode: OutputMode::Lines,
+            max_count: None,
+            line_number: false,
+            column: false,
+            color: ColorChoice::Auto,
+            before_context: 0,
+            after_context: 0,
+            encoding: None,
+            threads: None,
+            index: false,
+            build_index: None,
+            normalize: None,
+            separator: Separator::Line,
+            replace: None,
+            edit: None,
+            watch: None,
+            interactive: false,
+        };
+
+        // flags can appear anywhere in the command
+        // ex. $ cargo run --bin ch12_building_a_cli_tool -- -in -C 1 --regex "^How .*!$" ./assets/poem.txt ./src
+        let parsed = args::parse(args.get(1..).unwrap_or(&[]))?;
+        let mut has_pattern_flags = false;
+        for (name, value) in parsed.flags {
+            match name {
+                "regexp" => {
+                    has_pattern_flags = true;
+                    config.patterns.push(value.unwrap_or_default().to_string());
+                }
+                // one pattern per line
+                "file" => {
+                    has_pattern_flags = true;
+                    let path = value.unwrap_or_default();
+                    let contents = fs::read_to_string(path).map_err(|e| {
+                        ArgsError::Invalid(format!("can't read pattern file {}: {}", path, e))
+                    })?;
+                    config.patterns.extend(contents.lines().map(String::from));
+                }
+                "glob" => config.filter.add_glob(value.unwrap_or_default()),
+                "type" => config.filter.add_type(value.unwrap_or_default(), false)?,
+                "type-not" => config.filter.add_type(value.unwrap_or_default(), true)?,
+                "type-list" => return Err(ArgsError::TypeList),
+                "ignore-case" => config.case_sensitive = false,
+                "case-sensitive" => config.case_sensitive = true,
+                "regex" => config.regex = true,
+                "boolean" => config.boolean = true,
+                // like grep, -x wins over -w
+                "word-regexp" if config.boundary != Boundary::Line => {
+                    config.boundary = Boundary::Word
+                }
+                "word-regexp" => {}
+                "line-regexp" => config.boundary = Boundary::Line,
+                "multiline" => config.multiline = true,
+                "flex-space" => config.flex_space = true,
+                "fuzzy" => config.fuzzy = Some(args::number(name, value)?),
+                "rank" => config.rank = Some(value.map_or(Ok(10), |_| args::number(name, value))?),
+                "invert-match" => config.invert = true,
+                "count" => config.mode = OutputMode::Count,
+                "files-with-matches" => config.mode = OutputMode::FilesWithMatches,
+                "files-without-match" => config.mode = OutputMode::FilesWithoutMatches,
+                "quiet" => config.mode = OutputMode::Quiet,
+                "json" => config.mode = OutputMode::Json,
+                "max-count" => config.max_count = Some(args::number(name, value)?),
+                "line-number" => config.line_number = true,
+                // columns are only useful next to a line number
+                "column" => {
+                    config.line_number = true;
+                    config.column = true;
+                }
+                "after-context" => config.after_context = args::number(name, value)?,
+                "before-context" => config.before_context = args::number(name, value)?,
+                "context" => {
+                    config.before_context = args::number(name, value)?;
+                    config.after_context = config.before_context;
+                }
+                "color" => {
+                    config.color = value.map_or(Ok(ColorChoice::Auto), ColorChoice::parse)?
+                }
+                "encoding" => config.encoding = Some(Encoding::parse(value.unwrap_or_default())?),
+                "normalize" => config.normalize = Some(Form::parse(value.unwrap_or_default())?),
+                "paragraph" => config.separator = Separator::Paragraph,
+                "null-data" => config.separator = Separator::Nul,
+                "record-separator" => {
+                    config.separator = Separator::parse(value.unwrap_or_default())?
+                }
+                "replace" => config.replace = value.map(String::from),
+                // --dry-run wins, so nothing is written by accident
+                "in-place" if config.edit != Some(Edit::DryRun) => {
+                    config.edit = Some(Edit::InPlace {
+                        backup: value.map(String::from),
+                    })
+                }
+                "in-place" => {}
+                "dry-run" => config.edit = Some(Edit::DryRun),
+                "index" => config.index = true,
+                "build-index" => config.build_index = value,
+                // --follow wins, it's the more specific of the two
+                "watch" if config.watch != Some(Watch::Follow) => config.watch = Some(Watch::Rerun),
+                "watch" => {}
+                "follow" => config.watch = Some(Watch::Follow),
+                "interactive" => config.interactive = true,
+                "threads" => {
+                    config.threads = match args::number(name, value)? {
+                        0 => None,
+                        threads => Some(threads),
+                    }
+                }
+                "help" => return Err(ArgsError::Help),
+                "version" => return Err(ArgsError::Version),
+                _ => unreachable!("--{} is listed in args::FLAGS but not handled", name),
+            }
+        }
+
+        if config.build_index.is_some() {
+            if has_pattern_flags || !parsed.positional.is_empty() {
+                return Err("--build-index doesn't take a query or paths".into());
+            }
+            return Ok(config);
+        }
+
+        // without -e or -f the first arg is the query
+        let mut positional = parsed.positional.into_iter();
+        if !has_pattern_flags {
+            let query = positional.next().ok_or("not enough arguments")?;
+            config.patterns.push(query.to_string());
+        }
+        config.paths = positional.collect();
+
+        if config.edit.is_some() {
+            if config.replace.is_none() {
+                return Err("--in-place and --dry-run need --replace".into());
+            }
+            // other records would need their separators put back
+            if config.separator != Separator::Line {
+                return Err("--in-place and --dry-run only work on lines".into());
+            }
+            // the lines -v selects have no matches to replace
+            if config.invert {
+                return Err("--in-place and --dry-run can't be used with -v".into());
+            }
+        }
+
+        if config.fuzzy.is_some() && config.regex {
+            return Err("--fuzzy can't be used with --regex".into());
+        }
+        if config.boolean {
+            if config.regex || config.fuzzy.is_some() {
+                return Err("--boolean can't be used with --regex or --fuzzy".into());
+            }
+            if config.patterns.len() != 1 {
+                return Err("--boolean takes a single query".into());
+            }
+        }
+
+        if config.flex_space && (config.regex || config.fuzzy.is_some() || config.boolean) {
+            return Err("--flex-space can't be used with --regex, --fuzzy or --boolean".into());
+        }
+        if config.multiline {
+            // a whole file is one query for --boolean
+            if config.boolean || config.boundary == Boundary::Line {
+                return Err("--multiline can't be used with --boolean or --line-regexp".into());
+            }
+            if config.separator != Separator::Line {
+                return Err("--multiline only works on lines".into());
+            }
+            // these look at one line at a time
+            let per_line = config.edit.is_some()
+                || config.rank.is_some()
+                || config.watch == Some(Watch::Follow)
+                || config.interactive;
+            if per_line {
+                return Err(
+                    "--multiline can't be used with --in-place, --dry-run, --rank, --follow or --interactive"
+                        .into(),
+                );
+            }
+        }
+
+        if config.rank.is_some() {
+            if config.regex || config.fuzzy.is_some() || config.boolean {
+                return Err("--rank can't be used with --regex, --fuzzy or --boolean".into());
+            }
+            if config.invert || config.mode != OutputMode::Lines || config.replace.is_some() {
+                return Err("--rank only prints the best matches".into());
+            }
+            // the scores depend on how many files have each word, not only the ones left
+            if config.index {
+                return Err("--rank can't be used with --index".into());
+            }
+        }
+
+        if let Some(watch) = config.watch {
+            if config.paths.is_empty() || config.paths.contains(&STDIN) {
+                return Err("--watch and --follow need files to check for changes".into());
+            }
+            // the search would see its own writes as changes
+            if let Some(Edit::InPlace { .. }) = config.edit {
+                return Err("--watch and --follow can't be used with --in-place".into());
+            }
+            let prints_lines = config.mode == OutputMode::Lines || config.mode == OutputMode::Json;
+            if watch == Watch::Follow
+                && (!prints_lines || config.edit.is_some() || config.rank.is_some())
+            {
+                return Err("--follow only prints the lines it finds".into());
+            }
+        }
+
+        if config.interactive {
+            if config.paths.is_empty() || config.paths.contains(&STDIN) {
+                return Err("--interactive needs files to browse".into());
+            }
+            if config.patterns.len() != 1 {
+                return Err("--interactive takes a single query".into());
+            }
+            let other_output = config.mode != OutputMode::Lines
+                || config.replace.is_some()
+                || config.rank.is_some()
+                || config.watch.is_some();
+            if other_output || config.separator != Separator::Line {
+                return Err("--interactive only browses matching lines".into());
+            }
+        }
+
+        // search piped input when no files are given
+        if config.paths.is_empty() {
+            config.paths.push(STDIN);
+        }
+
+        Ok(config)
+    }
+
+    /// Checks if output needs the path of each file, when there can be more than one
+    pub fn with_filename(&self) -> bool {
+        self.paths.len() > 1 || self.paths.iter().any(|path| Path::new(path).is_dir())
+    }
+}
+
+/// How lines are checked for the patterns
+pub enum Matcher {
+    // a single query, with or without case
+    Literal(Finder),
+    // one regex per pattern
+    Regex(Vec<Regex>),
+    // several literal patterns at once
+    Multi(AhoCorasick),
+    // one approximate matcher per pattern
+    Fuzzy(Vec<Fuzzy>),
+    // literal terms combined with AND, OR and NOT
+    Boolean(Query),
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, Box<dyn Error>> {
+        // patterns are normalized the same way as the lines they're compared to
+        let patterns: Vec<String> = match config.normalize {
+            Some(form) => config
+                .patterns
+                .iter()
+                .map(|pattern| unicode::normalize(pattern, form))
+                .collect(),
+            None => config.patterns.clone(),
+        };
+
+        if config.boolean {
+            Ok(Matcher::Boolean(Query::new(
+                &patterns[0],
+                !config.case_sensitive,
+                config.boundary,
+            )?))
+        } else if let Some(max_distance) = config.fuzzy {
+            let fuzzies = patterns
+                .iter()
+                .map(|pattern| Fuzzy::new(pattern, max_distance, !config.case_sensitive))
+                .collect::<Result<_, _>>()?;
+            Ok(Matcher::Fuzzy(fuzzies))
+        } else if config.regex || config.flex_space {
+            let regexes = patterns
+                .iter()
+                .map(|pattern| {
+                    let pattern = if config.flex_space {
+                        regex::flexible_whitespace(pattern, !config.case_sensitive)
+                    } else {
+                        pattern.clone()
+                    };
+                    Regex::with_boundary(&pattern, !config.case_sensitive, config.boundary)
+                        .map(|regex| regex.multi_line(config.multiline))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(Matcher::Regex(regexes))
+        } else if patterns.len() != 1 {
+            Ok(Matcher::Multi(AhoCorasick::new(
+                &patterns,
+                !config.case_sensitive,
+            )))
+        } else {
+            Ok(Matcher::Literal(Finder::new(
+                &patterns[0],
+                !config.case_sensitive,
+            )))
+        }
+    }
+
+    /// Returns the first match that starts at or after the `start` byte
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        let (begin, end, pattern, distance) = match self {
+            Matcher::Literal(finder) => finder.find_at(line, start).map(|(b, e)| (b, e, 0, 0)),
+            // the leftmost, then longest, match of any of the regexes
+            Matcher::Regex(regexes) => regexes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, regex)| regex.find_at(line, start).map(|(b, e)| (b, e, i, 0)))
+                .min_by_key(|&(begin, end, i, _)| (begin, Reverse(end), i)),
+            Matcher::Multi(automaton) => {
+                automaton.find_at(line, start).map(|(b, e, i)| (b, e, i, 0))
+            }
+            // the leftmost, then closest, match of any of the patterns
+            Matcher::Fuzzy(fuzzies) => fuzzies
+                .iter()
+                .enumerate()
+                .filter_map(|(i, fuzzy)| fuzzy.find_at(line, start).map(|(b, e, d)| (b, e, i, d)))
+                .min_by_key(|&(begin, end, i, distance)| (begin, distance, Reverse(end), i)),
+            Matcher::Boolean(query) => query.find_at(line, start).map(|(b, e)| (b, e, 0, 0)),
+        }?;
+
+        Some(Hit {
+            distance,
+            ..Hit::new(line, begin, end, pattern)
         })
     }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let mut results = vec![];
+/// A line that matched the query
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    // counted from 1
+    pub line_number: usize,
+    pub line: &'a str,
+    pub hits: Vec<Hit>,
+}
+
+/// Where a pattern was found inside of a line
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+    pub bytes: Range<usize>,
+    // chars counted from 1
+    pub columns: Range<usize>,
+    // index into `Config.patterns`
+    pub pattern: usize,
+    // how many edits away from the pattern the match is, only set by --fuzzy
+    pub distance: usize,
+}
 
-    // separate text by line
-    for line in contents.lines() {
-        // push lines, with query, to results
-        if line.contains(query) {
-            results.push(line);
+impl Hit {
+    pub fn new(line: &str, start: usize, end: usize, pattern: usize) -> Hit {
+        let column = line[..start].chars().count() + 1;
+        Hit {
+            bytes: start..end,
+            columns: column..column + line[start..end].chars().count(),
+            pattern,
+            distance: 0,
         }
     }
+}
 
-    results
+/// Lazily yields every line that matches along with the location of each hit
+pub fn matches<'a: 'm, 'm>(
+    matcher: &'m Matcher,
+    contents: &'a str,
+) -> impl Iterator<Item = Match<'a>> + 'm {
+    contents.lines().enumerate().filter_map(move |(i, line)| {
+        let hits = find_hits(matcher, line, Boundary::Anywhere);
+        if hits.is_empty() {
+            None
+        } else {
+            Some(Match {
+                line_number: i + 1,
+                line,
+                hits,
+            })
+        }
+    })
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    let mut results = vec![];
+pub fn find_matches<'a>(matcher: &Matcher, contents: &'a str) -> Vec<Match<'a>> {
+    matches(matcher, contents).collect()
+}
 
-    // separate text by line
-    for line in contents.lines() {
-        // push lines, with query, to results
-        if line.to_lowercase().contains(&query) {
-            results.push(line);
+/// Decides which lines of the input are selected
+pub struct Searcher {
+    pub matcher: Matcher,
+    pub invert: bool,
+    pub max_count: Option<usize>,
+    pub encoding: Option<Encoding>,
+    pub normalize: Option<Form>,
+    pub boundary: Boundary,
+    pub multiline: bool,
+    pub separator: Separator,
+    pub replacement: Vec<u8>,
+    pub edit: Option<Edit>,
+}
+
+impl Searcher {
+    pub fn new(config: &Config) -> Result<Searcher, Box<dyn Error>> {
+        // listing files, or checking for any match, can stop at the first match,
+        // but every match of a file that's edited has to be replaced
+        let max_count = match config.mode {
+            OutputMode::Lines | OutputMode::Count | OutputMode::Json => config.max_count,
+            _ if config.edit.is_some() => config.max_count,
+            _ => Some(1),
+        };
+
+        Ok(Searcher {
+            matcher: Matcher::new(config)?,
+            invert: config.invert,
+            max_count,
+            encoding: config.encoding,
+            normalize: config.normalize,
+            boundary: config.boundary,
+            multiline: config.multiline,
+            separator: config.separator.clone(),
+            replacement: config.replace.clone().unwrap_or_default().into_bytes(),
+            edit: config.edit.clone(),
+        })
+    }
+
+    /// Searches one file, or stdin for `-`, and prints its totals
+    pub fn search_file<W: Write>(
+        &self,
+        file: &Path,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        // `-` reads from piped input. ex. $ cat ./assets/poem.txt | minigrep frog
+        if let Some(edit) = &self.edit {
+            self.edit_file(file, edit, printer)
+        } else if file == Path::new(STDIN) {
+            self.search_input(
+                "(standard input)",
+                Box::new(io::stdin().lock()),
+                false,
+                printer,
+            )
+        } else {
+            let reader = BufReader::new(File::open(file)?);
+            self.search_input(
+                &file.display().to_string(),
+                Box::new(reader),
+                false,
+                printer,
+            )
         }
     }
 
-    results
+    // Searches what's decompressed from gzip, and each file in a tar archive on its own,
+    // as `archive.tar:member/path`. `member` is set for the files inside an archive.
+    fn search_input<W: Write>(
+        &self,
+        path: &str,
+        reader: Box<dyn BufRead + '_>,
+        member: bool,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        let (format, reader) = archive::detect(reader)?;
+        match format {
+            Some(Format::Gzip) => {
+                let gzip = BufReader::new(archive::Gzip::new(reader)?);
+                self.search_input(path, Box::new(gzip), member, printer)
+            }
+            Some(Format::Tar) => {
+                let mut tar = archive::Tar::new(reader);
+                let mut count = 0;
+                while let Some(name) = tar.next_file()? {
+                    let path = format!("{}:{}", path, name);
+                    count += self.search_input(
+                        &path,
+                        Box::new(BufReader::new(&mut tar)),
+                        true,
+                        printer,
+                    )?;
+                }
+                Ok(count)
+            }
+            None => {
+                if member {
+                    printer.begin_member(path)?;
+                } else {
+                    printer.begin_file(path)?;
+                }
+                let count = self.search_reader(reader, printer)?;
+                printer.end_file(count)?;
+                Ok(count)
+            }
+        }
+    }
+
+    /// Searches any reader one record at a time, handing each record to the printer.
+    /// Records are lines unless another separator was asked for.
+    /// Only one record is held in memory, so input of any size can be searched.
+    /// Returns the number of selected records.
+    pub fn search_reader<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        let reader = encoding::decode(reader, self.encoding)?;
+        self.search_decoded(reader, 1, 0, printer)
+    }
+
+    /// Searches UTF-8 text whose first record is on `line_number` of a file, after `selected`
+    /// of the file's records were already selected, ex. the part that was appended to a
+    /// followed file. Returns the number of records selected in the whole file.
+    pub fn search_decoded<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        line_number: usize,
+        selected: usize,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        // like grep, a NUL byte means the file isn't text, unless it separates records
+        let nul_is_text = self.separator == Separator::Nul;
+        let mut binary = !nul_is_text && reader.fill_buf()?.contains(&0);
+        if self.multiline {
+            return self.search_multiline(reader, line_number, selected, binary, printer);
+        }
+
+        let mut records = Records::starting_at(reader, &self.separator, line_number);
+        let mut record = vec![];
+        let mut count = selected;
+
+        loop {
+            // after the last allowed match, only read what's needed for context
+            let limit_reached = self.max_count.is_some_and(|max| count >= max);
+            if limit_reached && !printer.wants_context() {
+                break;
+            }
+
+            let line_number = match records.next_record(&mut record)? {
+                Some(line_number) => line_number,
+                None => break,
+            };
+            let bytes = record.as_slice();
+            binary |= !nul_is_text && bytes.contains(&0);
+
+            let text = encoding::lossy_line(bytes);
+            let hits = self.hits(&text);
+
+            if hits.is_empty() == self.invert && !limit_reached {
+                count += 1;
+                // binary lines aren't printed, only that the file matched
+                if binary && printer.shows_lines() {
+                    printer.binary_file_matches()?;
+                    break;
+                }
+                printer.matched_line(line_number, bytes, &hits)?;
+            } else {
+                printer.other_line(line_number, bytes)?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    // Reads the whole input so matches can span lines, then hands over each line.
+    // A line is selected when any part of a match is on it.
+    fn search_multiline<R: BufRead, W: Write>(
+        &self,
+        mut reader: R,
+        line_number: usize,
+        selected: usize,
+        binary: bool,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+        let binary = binary || contents.contains(&0);
+        let text = encoding::lossy_line(&contents);
+        let hits = self.hits(&text);
+
+        // the first hit that can still be on this line or a later one
+        let mut next = 0;
+        let mut start = 0;
+        let mut count = selected;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let limit_reached = self.max_count.is_some_and(|max| count >= max);
+            if limit_reached && !printer.wants_context() {
+                break;
+            }
+
+            let end = start + line.len();
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            let content = content.strip_suffix('\r').unwrap_or(content);
+            // skip the matches that ended before this line,
+            // one that ends with a newline doesn't reach the next line
+            while hits.get(next).is_some_and(|hit| {
+                hit.bytes.end < start || hit.bytes.end == start && !hit.bytes.is_empty()
+            }) {
+                next += 1;
+            }
+            let touching: Vec<&Hit> = hits[next..]
+                .iter()
+                .take_while(|hit| hit.bytes.start < end)
+                .collect();
+
+            if touching.is_empty() == self.invert && !limit_reached {
+                count += 1;
+                if binary && printer.shows_lines() {
+                    printer.binary_file_matches()?;
+                    break;
+                }
+                // the part of each match that's on this line, to highlight
+                let content_end = start + content.len();
+                let line_hits: Vec<Hit> = touching
+                    .iter()
+                    .filter_map(|hit| {
+                        let from = hit.bytes.start.clamp(start, content_end) - start;
+                        let to = hit.bytes.end.min(content_end) - start;
+                        (from < to || hit.bytes.is_empty()).then(|| Hit {
+                            distance: hit.distance,
+                            ..Hit::new(content, from, to, hit.pattern)
+                        })
+                    })
+                    .collect();
+                printer.matched_line(line_number + i, content.as_bytes(), &line_hits)?;
+            } else {
+                printer.other_line(line_number + i, content.as_bytes())?;
+            }
+            start = end;
+        }
+
+        Ok(count)
+    }
+
+    /// Replaces the matches in a file, then writes it back or prints a diff.
+    /// Returns the number of lines that were selected.
+    pub fn edit_file<W: Write>(
+        &self,
+        file: &Path,
+        edit: &Edit,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        if file == Path::new(STDIN) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "standard input can't be edited",
+            ));
+        }
+        let original = fs::read(file)?;
+        // match offsets are only offsets into the file when it's read as it is
+        let utf16 = original.starts_with(&[0xff, 0xfe]) || original.starts_with(&[0xfe, 0xff]);
+        if utf16 || self.encoding.is_some_and(|e| e != Encoding::Utf8) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "only UTF-8 files can be edited",
+            ));
+        }
+
+        let path = file.display().to_string();
+        printer.begin_file(&path)?;
+        // binary files are left alone
+        if original.contains(&0) {
+            printer.end_file(0)?;
+            return Ok(0);
+        }
+
+        let lines: Vec<&[u8]> = original.split_inclusive(|&b| b == b'\n').collect();
+        let mut changes = vec![];
+        let mut count = 0;
+        for (i, &line) in lines.iter().enumerate() {
+            if self.max_count.is_some_and(|max| count >= max) {
+                break;
+            }
+
+            // the line ending is kept as it is
+            let bytes = line.strip_suffix(b"\n").unwrap_or(line);
+            let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+            let hits = self.hits(&encoding::lossy_line(bytes));
+            if !hits.is_empty() {
+                count += 1;
+                let mut replaced = replace::replace_hits(bytes, &hits, &self.replacement);
+                replaced.extend_from_slice(&line[bytes.len()..]);
+                if replaced != line {
+                    changes.push((i, replaced));
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            match edit {
+                Edit::DryRun => printer.diff(&replace::unified_diff(&path, &lines, &changes))?,
+                Edit::InPlace { backup } => {
+                    let mut edited = Vec::with_capacity(original.len());
+                    let mut changes = changes.iter().peekable();
+                    for (i, &line) in lines.iter().enumerate() {
+                        match changes.next_if(|(index, _)| *index == i) {
+                            Some((_, replaced)) => edited.extend_from_slice(replaced),
+                            None => edited.extend_from_slice(line),
+                        }
+                    }
+                    replace::write_atomically(file, &edited, backup.as_deref())?;
+                }
+            }
+        }
+
+        printer.end_file(count)?;
+        Ok(count)
+    }
+
+    /// Finds where the patterns are in a line, keeping to the word or line boundaries
+    pub fn hits(&self, line: &str) -> Vec<Hit> {
+        let form = match self.normalize {
+            Some(form) => form,
+            None => return find_hits(&self.matcher, line, self.boundary),
+        };
+
+        // match against the normalized line, then point the hits back at the original line
+        let normalized = Normalized::new(line, form);
+        find_hits(&self.matcher, &normalized.text, self.boundary)
+            .into_iter()
+            .map(|hit| {
+                let bytes = normalized.source(hit.bytes);
+                Hit {
+                    distance: hit.distance,
+                    ..Hit::new(line, bytes.start, bytes.end, hit.pattern)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Where a match has to start and end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    Anywhere,
+    // not next to a letter, digit or `_`
+    Word,
+    // the whole line
+    Line,
+}
+
+impl Boundary {
+    pub fn allows(self, line: &str, bytes: &Range<usize>) -> bool {
+        match self {
+            Boundary::Anywhere => true,
+            Boundary::Word => {
+                let before = line[..bytes.start].chars().next_back();
+                let after = line[bytes.end..].chars().next();
+                !before.is_some_and(unicode::is_word_char)
+                    && !after.is_some_and(unicode::is_word_char)
+            }
+            Boundary::Line => bytes.start == 0 && bytes.end == line.len(),
+        }
+    }
+}
+
+fn find_hits(matcher: &Matcher, line: &str, boundary: Boundary) -> Vec<Hit> {
+    let mut hits = vec![];
+    let mut start = 0;
+    // a boolean query keeps its terms to the boundary itself, and a line selected for what
+    // it doesn't contain gets an empty match even where a word starts
+    let boundary = match matcher {
+        Matcher::Boolean(_) => Boundary::Anywhere,
+        _ => boundary,
+    };
+
+    while let Some(hit) = matcher.find_at(line, start) {
+        // a later match may still fit, ex. "us" in "usual us"
+        if !boundary.allows(line, &hit.bytes) {
+            match line[hit.bytes.start..].chars().next() {
+                Some(c) => start = hit.bytes.start + c.len_utf8(),
+                None => break,
+            }
+            continue;
+        }
+
+        // step past empty matches so the loop always moves forward
+        start = if !hit.bytes.is_empty() {
+            hit.bytes.end
+        } else {
+            match line[hit.bytes.end..].chars().next() {
+                Some(c) => hit.bytes.end + c.len_utf8(),
+                None => line.len() + 1,
+            }
+        };
+
+        hits.push(hit);
+        if start > line.len() {
+            break;
+        }
+    }
+
+    hits
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let matcher = Matcher::Literal(Finder::new(query, false));
+    matches(&matcher, contents).map(|m| m.line).collect()
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let matcher = Matcher::Literal(Finder::new(query, true));
+    matches(&matcher, contents).map(|m| m.line).collect()
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<&'a str> {
+    contents
+        .lines()
+        .filter(|line| regex.is_match(line))
+        .collect()
 }
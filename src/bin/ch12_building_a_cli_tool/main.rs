@@ -1,25 +1,66 @@
 // cargo run --bin ch12_building_a_cli_tool test ./assets/poem.txt
+pub mod aho_corasick;
+pub mod archive;
+pub mod args;
+pub mod deflate;
+pub mod encoding;
+pub mod fuzzy;
+pub mod glob;
+pub mod index;
+pub mod interactive;
+pub mod json;
 pub mod lib;
+pub mod printer;
+pub mod query;
+pub mod rank;
+pub mod records;
+pub mod regex;
+pub mod replace;
+pub mod substring;
 mod tests;
+pub mod unicode;
+mod unicode_tables;
+pub mod walk;
+pub mod watch;
+use args::ArgsError;
 use lib::Config;
 use std::env;
 use std::process;
+use watch::Watch;
 
 fn main() {
     // return cli args as a vector of strings
     let args: Vec<String> = env::args().collect();
-    println!("Captured Args: {:?}", args);
 
-    // extract the regex query and filename to scan
+    // extract the query, flags and paths to scan
     let config = Config::new(&args).unwrap_or_else(|err| {
+        if let ArgsError::Help | ArgsError::Version | ArgsError::TypeList = err {
+            println!("{}", err);
+            process::exit(0);
+        }
+
         // prints error to Standard Error instead of Standard Output
         eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+        process::exit(lib::EXIT_ERROR);
     });
 
-    // print search matches
-    if let Err(e) = lib::run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    // ex. $ cargo run --bin ch12_building_a_cli_tool -- --build-index ./src
+    if let Some(dir) = config.build_index {
+        process::exit(index::command(dir));
+    }
+
+    // print search matches, then exit like grep does, unless the search keeps going
+    let result = match config.watch {
+        _ if config.interactive => interactive::run(config),
+        Some(Watch::Rerun) => watch::watch(&config),
+        Some(Watch::Follow) => watch::follow(&config),
+        None => lib::run(&config),
+    };
+    match result {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("Application error: {}", e);
+            process::exit(lib::EXIT_ERROR);
+        }
     }
 }
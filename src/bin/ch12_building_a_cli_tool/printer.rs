@@ -0,0 +1,541 @@
+// Formats search results the way grep does.
+// ex. path:12:5:matching line (`:` after matches, `-` after context lines)
+use super::json;
+use super::lib::{Config, Hit};
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+
+// ANSI escape codes, using the same colors as grep
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const PATH_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const REMOVED_COLOR: &str = "\x1b[31m";
+const ADDED_COLOR: &str = "\x1b[32m";
+const HUNK_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// When to highlight output with ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(when: &str) -> Result<ColorChoice, &'static str> {
+        match when {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err("--color must be auto, always or never"),
+        }
+    }
+
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // only color a terminal, unless NO_COLOR is set (https://no-color.org)
+            ColorChoice::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                !no_color && io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// What to print for each file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    // the selected lines themselves
+    Lines,
+    // how many lines were selected
+    Count,
+    // only the paths of files with/without a selected line
+    FilesWithMatches,
+    FilesWithoutMatches,
+    // nothing, only the exit code matters
+    Quiet,
+    // one JSON object per line for every event, for other programs to read
+    Json,
+}
+
+/// Totals reported by `--json`
+#[derive(Debug, Default, Clone, Copy)]
+struct Stats {
+    matched_lines: usize,
+    matches: usize,
+}
+
+/// Where the context lines of a file were left, so a later search of more of it carries on
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    before: VecDeque<(usize, Vec<u8>)>,
+    after_remaining: usize,
+    lines_seen: usize,
+    last_printed: usize,
+}
+
+impl Context {
+    /// Checks if lines after the last selected line still need to be printed
+    pub fn owes_lines(&self) -> bool {
+        self.after_remaining > 0
+    }
+}
+
+/// Writes matches, and the lines around them, to any output.
+/// Lines are handed over one at a time, so only the context lines are kept in memory.
+/// With another record separator, each "line" is a whole record.
+pub struct Printer<W: Write> {
+    out: W,
+    mode: OutputMode,
+    with_filename: bool,
+    // for this file, set even without `with_filename` for the files inside an archive
+    show_path: bool,
+    line_number: bool,
+    column: bool,
+    // set when searching for several patterns, to show which one matched
+    patterns: Vec<String>,
+    // show how far each match is from its pattern
+    fuzzy: bool,
+    color: bool,
+    before_context: usize,
+    after_context: usize,
+    // written after each line
+    terminator: &'static [u8],
+    // printed in place of each match
+    replacement: Option<Vec<u8>>,
+    // the file being printed
+    path: String,
+    // recent lines that may be needed as context for the next match
+    before: VecDeque<(usize, Vec<u8>)>,
+    // context lines still owed to the last match
+    after_remaining: usize,
+    // how many lines of this file have been handed over, lines are only adjacent to each other
+    // when they're handed over one after the other since a record can span several lines
+    lines_seen: usize,
+    // the count of `lines_seen` when the last line of this file was printed
+    last_printed: usize,
+    // used to know when a `--` separator is needed
+    printed_any: bool,
+    file_stats: Stats,
+    total_stats: Stats,
+    searches: usize,
+    searches_with_match: usize,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, config: &Config, with_filename: bool) -> Printer<W> {
+        Printer {
+            out,
+            mode: config.mode,
+            with_filename,
+            show_path: with_filename,
+            line_number: config.line_number,
+            column: config.column,
+            patterns: if config.patterns.len() > 1 {
+                config.patterns.clone()
+            } else {
+                vec![]
+            },
+            fuzzy: config.fuzzy.is_some(),
+            color: config.color.enabled(),
+            before_context: config.before_context,
+            after_context: config.after_context,
+            terminator: config.separator.terminator(),
+            replacement: config.replace.clone().map(String::into_bytes),
+            path: String::new(),
+            before: VecDeque::new(),
+            after_remaining: 0,
+            lines_seen: 0,
+            last_printed: 0,
+            printed_any: false,
+            file_stats: Stats::default(),
+            total_stats: Stats::default(),
+            searches: 0,
+            searches_with_match: 0,
+        }
+    }
+
+    pub fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.path = path.to_string();
+        self.show_path = self.with_filename;
+        self.before.clear();
+        self.after_remaining = 0;
+        self.lines_seen = 0;
+        self.last_printed = 0;
+        self.file_stats = Stats::default();
+
+        if self.mode == OutputMode::Json {
+            let path = json::data(self.path.as_bytes());
+            writeln!(
+                self.out,
+                "{{\"type\":\"begin\",\"data\":{{\"path\":{}}}}}",
+                path
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Starts the next part of a file, with the context lines it was left with
+    pub fn resume_file(&mut self, path: &str, context: Context) -> io::Result<()> {
+        self.begin_file(path)?;
+        self.before = context.before;
+        self.after_remaining = context.after_remaining;
+        self.lines_seen = context.lines_seen;
+        self.last_printed = context.last_printed;
+        Ok(())
+    }
+
+    /// Returns the context lines of the file, for `resume_file` to carry on from
+    pub fn suspend_file(&mut self) -> Context {
+        Context {
+            before: std::mem::take(&mut self.before),
+            after_remaining: self.after_remaining,
+            lines_seen: self.lines_seen,
+            last_printed: self.last_printed,
+        }
+    }
+
+    /// Starts a file inside an archive, whose path is always printed so its archive is known
+    pub fn begin_member(&mut self, path: &str) -> io::Result<()> {
+        self.begin_file(path)?;
+        self.show_path = true;
+        Ok(())
+    }
+
+    /// Prints per file totals once the whole file has been searched
+    pub fn end_file(&mut self, count: usize) -> io::Result<()> {
+        let (start, end) = self.colors(PATH_COLOR);
+        self.searches += 1;
+        if count > 0 {
+            self.searches_with_match += 1;
+        }
+
+        match self.mode {
+            OutputMode::Json => writeln!(
+                self.out,
+                "{{\"type\":\"end\",\"data\":{{\"path\":{},\"stats\":{}}}}}",
+                json::data(self.path.as_bytes()),
+                self.stats_json(self.file_stats)
+            ),
+            OutputMode::Count if self.show_path => {
+                writeln!(self.out, "{}{}{}:{}", start, self.path, end, count)
+            }
+            OutputMode::Count => writeln!(self.out, "{}", count),
+            OutputMode::FilesWithMatches if count > 0 => {
+                writeln!(self.out, "{}{}{}", start, self.path, end)
+            }
+            OutputMode::FilesWithoutMatches if count == 0 => {
+                writeln!(self.out, "{}{}{}", start, self.path, end)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Prints a summary of the whole search, only used by `--json`
+    pub fn finish(&mut self, elapsed: Duration) -> io::Result<()> {
+        if self.mode != OutputMode::Json {
+            return Ok(());
+        }
+
+        let elapsed = format!(
+            "{{\"secs\":{},\"nanos\":{},\"human\":\"{:.6}s\"}}",
+            elapsed.as_secs(),
+            elapsed.subsec_nanos(),
+            elapsed.as_secs_f64()
+        );
+        let stats = format!(
+            "{{\"searches\":{},\"searches_with_match\":{},\"matched_lines\":{},\"matches\":{}}}",
+            self.searches,
+            self.searches_with_match,
+            self.total_stats.matched_lines,
+            self.total_stats.matches
+        );
+        writeln!(
+            self.out,
+            "{{\"type\":\"summary\",\"data\":{{\"elapsed_total\":{},\"stats\":{}}}}}",
+            elapsed, stats
+        )
+    }
+
+    fn stats_json(&self, stats: Stats) -> String {
+        format!(
+            "{{\"matched_lines\":{},\"matches\":{}}}",
+            stats.matched_lines, stats.matches
+        )
+    }
+
+    /// Adds the output and totals of a printer that searched other files on another thread
+    pub fn append(&mut self, other: Printer<Vec<u8>>) -> io::Result<()> {
+        // the other printer didn't know about the groups printed before its own
+        if self.separates_groups() && self.printed_any && other.printed_any {
+            writeln!(self.out, "--")?;
+        }
+        self.out.write_all(&other.out)?;
+        self.printed_any |= other.printed_any;
+
+        self.total_stats.matched_lines += other.total_stats.matched_lines;
+        self.total_stats.matches += other.total_stats.matches;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        Ok(())
+    }
+
+    // `--` goes between groups of lines when there's context around them
+    fn separates_groups(&self) -> bool {
+        self.mode == OutputMode::Lines && (self.before_context > 0 || self.after_context > 0)
+    }
+
+    /// Checks if the lines themselves are printed, and not only totals or paths
+    pub fn shows_lines(&self) -> bool {
+        self.mode == OutputMode::Lines || self.mode == OutputMode::Json
+    }
+
+    /// Checks if lines after the last selected line still need to be printed
+    pub fn wants_context(&self) -> bool {
+        self.shows_lines() && self.after_remaining > 0
+    }
+
+    /// Prints a note instead of the lines of a binary file, since they'd garble the terminal
+    pub fn binary_file_matches(&mut self) -> io::Result<()> {
+        if self.mode != OutputMode::Lines {
+            return Ok(());
+        }
+        writeln!(self.out, "Binary file {} matches", self.path)
+    }
+
+    /// Prints a selected line, along with any context before it.
+    /// Lines are raw bytes so text that isn't valid UTF-8 comes out unchanged.
+    pub fn matched_line(
+        &mut self,
+        line_number: usize,
+        line: &[u8],
+        hits: &[Hit],
+    ) -> io::Result<()> {
+        for stats in [&mut self.file_stats, &mut self.total_stats].iter_mut() {
+            stats.matched_lines += 1;
+            stats.matches += hits.len();
+        }
+        if !self.shows_lines() {
+            return Ok(());
+        }
+        self.lines_seen += 1;
+
+        // groups that don't touch the previous one get a separator
+        let first = self.lines_seen - self.before.len();
+        if self.separates_groups()
+            && self.printed_any
+            && (self.last_printed == 0 || first > self.last_printed + 1)
+        {
+            writeln!(self.out, "--")?;
+        }
+
+        while let Some((n, before)) = self.before.pop_front() {
+            self.print_context(n, &before)?;
+        }
+        self.print_match(line_number, line, hits)?;
+
+        self.last_printed = self.lines_seen;
+        self.after_remaining = self.after_context;
+        Ok(())
+    }
+
+    /// Prints a line that wasn't selected if it's context for a selected line
+    pub fn other_line(&mut self, line_number: usize, line: &[u8]) -> io::Result<()> {
+        if !self.shows_lines() {
+            return Ok(());
+        }
+        self.lines_seen += 1;
+
+        if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            self.last_printed = self.lines_seen;
+            return self.print_context(line_number, line);
+        }
+
+        if self.before_context > 0 {
+            if self.before.len() == self.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back((line_number, line.to_vec()));
+        }
+        Ok(())
+    }
+
+    fn print_match(&mut self, line_number: usize, line: &[u8], hits: &[Hit]) -> io::Result<()> {
+        self.printed_any = true;
+        if self.mode == OutputMode::Json {
+            return self.print_json_line("match", line_number, line, hits);
+        }
+
+        self.print_prefix(line_number, ':')?;
+        if self.column {
+            let column = hits.first().map_or(1, |hit| hit.columns.start);
+            write!(self.out, "{}:", column)?;
+        }
+        if !self.patterns.is_empty() && !hits.is_empty() {
+            let mut names: Vec<&str> = vec![];
+            for hit in hits {
+                let name = self.patterns[hit.pattern].as_str();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            write!(self.out, "{}:", names.join(","))?;
+        }
+        // ex. ~1,0: for a match 1 edit away and an exact one
+        if self.fuzzy && !hits.is_empty() {
+            let distances: Vec<String> = hits.iter().map(|hit| hit.distance.to_string()).collect();
+            write!(self.out, "~{}:", distances.join(","))?;
+        }
+        self.print_highlighted(line, hits)?;
+        self.out.write_all(self.terminator)
+    }
+
+    fn print_highlighted(&mut self, line: &[u8], hits: &[Hit]) -> io::Result<()> {
+        // wrap each hit in color, or swap it for the replacement, leaving the text between them alone
+        let mut written = 0;
+        let (start, end) = self.colors(MATCH_COLOR);
+        let replacing = self.replacement.is_some();
+        if self.color || replacing {
+            // an empty match still gets a replacement, ex. `--regex ^ --replace "> "`
+            for hit in hits.iter().filter(|hit| replacing || !hit.bytes.is_empty()) {
+                self.out.write_all(&line[written..hit.bytes.start])?;
+                let text = self
+                    .replacement
+                    .as_deref()
+                    .unwrap_or(&line[hit.bytes.clone()]);
+                self.out.write_all(start.as_bytes())?;
+                self.out.write_all(text)?;
+                self.out.write_all(end.as_bytes())?;
+                written = hit.bytes.end;
+            }
+        }
+
+        self.out.write_all(&line[written..])
+    }
+
+    /// Prints a document found by `--rank`, its score and the line that best matches
+    /// ex. 1.234 poem.txt:7:How public, like a frog
+    pub fn ranked(
+        &mut self,
+        score: f64,
+        path: &str,
+        line_number: usize,
+        snippet: &[u8],
+        hits: &[Hit],
+    ) -> io::Result<()> {
+        self.printed_any = true;
+        write!(self.out, "{:.3} ", score)?;
+        let (start, end) = self.colors(PATH_COLOR);
+        write!(self.out, "{}{}{}:", start, path, end)?;
+        let (start, end) = self.colors(LINE_NUMBER_COLOR);
+        write!(self.out, "{}{}{}:", start, line_number, end)?;
+        self.print_highlighted(snippet, hits)?;
+        // the snippet is a single line, whatever the records are
+        writeln!(self.out)
+    }
+
+    /// Prints the changes that editing a file would make
+    pub fn diff(&mut self, diff: &[u8]) -> io::Result<()> {
+        if self.mode != OutputMode::Lines {
+            return Ok(());
+        }
+        self.printed_any = true;
+
+        for line in diff.split_inclusive(|&b| b == b'\n') {
+            let color = match line.first() {
+                _ if line.starts_with(b"---") || line.starts_with(b"+++") => "",
+                Some(b'-') => REMOVED_COLOR,
+                Some(b'+') => ADDED_COLOR,
+                Some(b'@') => HUNK_COLOR,
+                _ => "",
+            };
+            let (start, end) = self.colors(color);
+            if color.is_empty() {
+                self.out.write_all(line)?;
+            } else {
+                let text = line.strip_suffix(b"\n").unwrap_or(line);
+                self.out.write_all(start.as_bytes())?;
+                self.out.write_all(text)?;
+                self.out.write_all(end.as_bytes())?;
+                self.out.write_all(&line[text.len()..])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn print_context(&mut self, line_number: usize, line: &[u8]) -> io::Result<()> {
+        if self.mode == OutputMode::Json {
+            return self.print_json_line("context", line_number, line, &[]);
+        }
+
+        self.print_prefix(line_number, '-')?;
+        self.out.write_all(line)?;
+        self.out.write_all(self.terminator)
+    }
+
+    // ex. {"type":"match","data":{"path":{"text":"poem.txt"},"lines":{"text":"..."},
+    //     "line_number":7,"submatches":[{"match":{"text":"frog"},"start":19,"end":23,"pattern":0}]}}
+    fn print_json_line(
+        &mut self,
+        kind: &str,
+        line_number: usize,
+        line: &[u8],
+        hits: &[Hit],
+    ) -> io::Result<()> {
+        let submatches: Vec<String> = hits
+            .iter()
+            .map(|hit| {
+                let distance = if self.fuzzy {
+                    format!(",\"distance\":{}", hit.distance)
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{{\"match\":{},\"start\":{},\"end\":{},\"pattern\":{}{}}}",
+                    json::data(&line[hit.bytes.clone()]),
+                    hit.bytes.start,
+                    hit.bytes.end,
+                    hit.pattern,
+                    distance
+                )
+            })
+            .collect();
+
+        writeln!(
+            self.out,
+            "{{\"type\":{},\"data\":{{\"path\":{},\"lines\":{},\"line_number\":{},\"submatches\":[{}]}}}}",
+            json::string(kind),
+            json::data(self.path.as_bytes()),
+            json::data(line),
+            line_number,
+            submatches.join(",")
+        )
+    }
+
+    fn print_prefix(&mut self, line_number: usize, separator: char) -> io::Result<()> {
+        if self.show_path {
+            let (start, end) = self.colors(PATH_COLOR);
+            write!(self.out, "{}{}{}{}", start, self.path, end, separator)?;
+        }
+        if self.line_number {
+            let (start, end) = self.colors(LINE_NUMBER_COLOR);
+            write!(self.out, "{}{}{}{}", start, line_number, end, separator)?;
+        }
+        Ok(())
+    }
+
+    // escape codes to wrap text with, or nothing when color is off
+    fn colors(&self, color: &'static str) -> (&'static str, &'static str) {
+        if self.color {
+            (color, RESET)
+        } else {
+            ("", "")
+        }
+    }
+}
@@ -0,0 +1,345 @@
+// A boolean query language for minigrep's `--boolean` mode.
+// Each term is found with the same literal search as a plain query, then the
+// terms are combined to decide if a line is selected.
+//
+// Supported syntax, from the loosest binding to the tightest:
+// - `a OR b`
+// - `a AND b`, or just `a b`
+// - `NOT a`
+// - words, "quoted phrases" (with \" and \\ escapes) and (groups)
+// ex. nobody AND NOT frog
+//     "public, like" OR (dreary bog)
+// The operators are only recognized in uppercase, quote them to search for the words.
+// With -w or -x each term has to be a whole word or line, including the negated ones.
+use super::index::Plan;
+use super::lib::Boundary;
+use super::substring::Finder;
+use std::cmp::Reverse;
+use std::error::Error;
+use std::fmt;
+
+/// A parsed query, ready to check lines against
+#[derive(Debug)]
+pub struct Query {
+    // the text of each term, and how it's found
+    words: Vec<String>,
+    terms: Vec<Finder>,
+    // the terms that aren't negated, so their matches can be shown
+    positive: Vec<usize>,
+    root: Node,
+    boundary: Boundary,
+}
+
+/// Describes why a query failed to parse, and where
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    pub query: String,
+    // chars counted from 1
+    pub column: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // point at the mistake under the query
+        // ex. invalid query at column 8: expected a term after AND
+        //       nobody AND
+        //                  ^
+        write!(
+            f,
+            "invalid query at column {}: {}\n  {}\n  {:>width$}",
+            self.column,
+            self.message,
+            self.query,
+            "^",
+            width = self.column
+        )
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Debug)]
+enum Node {
+    // index into `Query.terms`
+    Term(usize),
+    Not(Box<Node>),
+    And(Vec<Node>),
+    Or(Vec<Node>),
+}
+
+impl Node {
+    fn eval(&self, found: &[bool]) -> bool {
+        match self {
+            Node::Term(i) => found[*i],
+            Node::Not(node) => !node.eval(found),
+            Node::And(nodes) => nodes.iter().all(|node| node.eval(found)),
+            Node::Or(nodes) => nodes.iter().any(|node| node.eval(found)),
+        }
+    }
+
+    fn plan(&self, words: &[String], term: &dyn Fn(&str) -> Plan) -> Plan {
+        match self {
+            Node::Term(i) => term(&words[*i]),
+            // a file without a term can have lines without it
+            Node::Not(_) => Plan::All,
+            Node::And(nodes) => Plan::And(nodes.iter().map(|n| n.plan(words, term)).collect()),
+            Node::Or(nodes) => Plan::Or(nodes.iter().map(|n| n.plan(words, term)).collect()),
+        }
+    }
+}
+
+impl Query {
+    pub fn new(query: &str, ignore_case: bool, boundary: Boundary) -> Result<Query, QueryError> {
+        let mut parser = Parser {
+            query,
+            tokens: tokenize(query)?,
+            pos: 0,
+            terms: vec![],
+            positive: vec![],
+            negated: false,
+        };
+        let root = parser.parse_or()?;
+        match parser.peek() {
+            (Token::End, _) => {}
+            (Token::Close, column) => return Err(error(query, column, "unmatched )")),
+            (_, column) => return Err(error(query, column, "expected AND, OR or the end")),
+        }
+
+        Ok(Query {
+            terms: parser
+                .terms
+                .iter()
+                .map(|term| Finder::new(term, ignore_case))
+                .collect(),
+            words: parser.terms,
+            positive: parser.positive,
+            root,
+            boundary,
+        })
+    }
+
+    /// Works out which trigrams a file needs to match, given the plan for each term
+    pub fn plan(&self, term: &dyn Fn(&str) -> Plan) -> Plan {
+        self.root.plan(&self.words, term)
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        let found: Vec<bool> = (0..self.terms.len())
+            .map(|i| self.find_term(i, line, 0).is_some())
+            .collect();
+        self.root.eval(&found)
+    }
+
+    /// Returns the byte range of the first term that isn't negated at or after `start`,
+    /// if the line matches the query. A line that only matches because of what it doesn't
+    /// contain gets an empty match at its start.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        if !self.is_match(line) {
+            return None;
+        }
+
+        self.positive
+            .iter()
+            .filter_map(|&i| self.find_term(i, line, start))
+            .min_by_key(|&(begin, end)| (begin, Reverse(end)))
+            .or(if start == 0 { Some((0, 0)) } else { None })
+    }
+
+    // the first match of a term at or after `start` that keeps to the boundary
+    fn find_term(&self, i: usize, line: &str, mut start: usize) -> Option<(usize, usize)> {
+        loop {
+            let (begin, end) = self.terms[i].find_at(line, start)?;
+            if self.boundary.allows(line, &(begin..end)) {
+                return Some((begin, end));
+            }
+            // a later match may still fit, ex. "us" in "usual us"
+            start = begin + line[begin..].chars().next()?.len_utf8();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    End,
+}
+
+fn error(query: &str, column: usize, message: &str) -> QueryError {
+    QueryError {
+        message: message.to_string(),
+        query: query.to_string(),
+        column,
+    }
+}
+
+// splits the query into tokens, each with the column it starts at
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().enumerate().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let column = i + 1;
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push((Token::Open, column)),
+            ')' => tokens.push((Token::Close, column)),
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => {
+                            if let Some((_, c)) = chars.next() {
+                                phrase.push(c);
+                            }
+                        }
+                        Some((_, c)) => phrase.push(c),
+                        None => {
+                            return Err(error(
+                                query,
+                                column,
+                                "this phrase is missing its closing \"",
+                            ))
+                        }
+                    }
+                }
+                if phrase.is_empty() {
+                    return Err(error(query, column, "empty phrase"));
+                }
+                tokens.push((Token::Term(phrase), column));
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                };
+                tokens.push((token, column));
+            }
+        }
+    }
+
+    tokens.push((Token::End, query.chars().count() + 1));
+    Ok(tokens)
+}
+
+struct Parser<'q> {
+    query: &'q str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    terms: Vec<String>,
+    positive: Vec<usize>,
+    // set while parsing under an odd number of NOTs
+    negated: bool,
+}
+
+impl<'q> Parser<'q> {
+    fn peek(&self) -> (Token, usize) {
+        self.tokens[self.pos].clone()
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.peek();
+        if token.0 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Node, QueryError> {
+        let mut nodes = vec![self.parse_and()?];
+        while self.peek().0 == Token::Or {
+            self.next();
+            nodes.push(self.parse_and()?);
+        }
+
+        Ok(if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Node::Or(nodes)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Node, QueryError> {
+        let mut nodes = vec![self.parse_not()?];
+        loop {
+            match self.peek().0 {
+                Token::And => {
+                    self.next();
+                }
+                // terms next to each other must both match
+                Token::Term(_) | Token::Not | Token::Open => {}
+                _ => break,
+            }
+            nodes.push(self.parse_not()?);
+        }
+
+        Ok(if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Node::And(nodes)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Node, QueryError> {
+        if self.peek().0 != Token::Not {
+            return self.parse_term();
+        }
+
+        self.next();
+        self.negated = !self.negated;
+        let node = self.parse_not();
+        self.negated = !self.negated;
+        Ok(Node::Not(Box::new(node?)))
+    }
+
+    fn parse_term(&mut self) -> Result<Node, QueryError> {
+        let before = self.pos.checked_sub(1).map(|i| self.tokens[i].0.clone());
+        match self.next() {
+            (Token::Term(term), _) => {
+                let i = self.terms.len();
+                self.terms.push(term);
+                if !self.negated {
+                    self.positive.push(i);
+                }
+                Ok(Node::Term(i))
+            }
+            (Token::Open, open) => {
+                let node = self.parse_or()?;
+                match self.next() {
+                    (Token::Close, _) => Ok(node),
+                    (_, column) => Err(error(
+                        self.query,
+                        column,
+                        &format!("expected ) to close the ( at column {}", open),
+                    )),
+                }
+            }
+            (_, column) => {
+                let message = match before {
+                    Some(Token::And) => "expected a term after AND",
+                    Some(Token::Or) => "expected a term after OR",
+                    Some(Token::Not) => "expected a term after NOT",
+                    Some(Token::Open) => "expected a term after (",
+                    _ => "expected a term",
+                };
+                Err(error(self.query, column, message))
+            }
+        }
+    }
+}
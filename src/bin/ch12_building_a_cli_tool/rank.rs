@@ -0,0 +1,312 @@
+// Ranks files, or the records in them, by how relevant they are to a query of several words,
+// instead of listing every line that matches.
+// Each document gets a BM25 score, the sum over the words of the query of
+//   idf * tf * (K1 + 1) / (tf + K1 * (1 - B + B * length / average length))
+// where tf is how often the word is in the document, and idf is higher for rarer words.
+// So documents that use the rare words of the query often come first,
+// and long documents don't win only because they have more words.
+// ex. $ cargo run --bin ch12_building_a_cli_tool -- --rank -p "nobody public frog" ./assets
+use super::archive::{self, Format};
+use super::encoding;
+use super::lib::{Config, Hit, EXIT_ERROR, EXIT_MATCH, EXIT_NO_MATCH, STDIN};
+use super::printer::Printer;
+use super::records::{Records, Separator};
+use super::unicode;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// how quickly more of the same word stops adding to the score
+const K1: f64 = 1.2;
+// how much a document's length counts against it, from 0 to 1
+const B: f64 = 0.75;
+// snippets longer than this are cut down to the part around the first word of the query
+const SNIPPET_CHARS: usize = 100;
+
+/// Splits text into case folded words, with the bytes each came from.
+/// Words are folded like `search_case_insensitive` folds its query and lines.
+pub fn words(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, unicode::is_word_char(c)) {
+            (None, true) => start = Some(i),
+            (Some(begin), false) => {
+                words.push((begin..i, unicode::fold_str(&text[begin..i])));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// A document with at least one word of the query in it
+#[derive(Debug)]
+pub struct Document {
+    pub path: String,
+    // the line with the most different words of the query, and its number counted from 1
+    pub snippet: String,
+    pub line_number: usize,
+    // how many words the document has, and how often it has each word of the query
+    length: usize,
+    frequencies: Vec<usize>,
+}
+
+/// Collects the statistics of every document it's given, then scores them against the query
+#[derive(Debug)]
+pub struct Ranker {
+    // the different words of the query
+    terms: Vec<String>,
+    documents: Vec<Document>,
+    // across every document, including the ones without the query
+    document_count: usize,
+    total_length: usize,
+    // how many documents each word of the query is in
+    document_frequencies: Vec<usize>,
+}
+
+impl Ranker {
+    pub fn new(query: &str) -> Ranker {
+        let mut terms: Vec<String> = vec![];
+        for (_, word) in words(query) {
+            if !terms.contains(&word) {
+                terms.push(word);
+            }
+        }
+
+        Ranker {
+            document_frequencies: vec![0; terms.len()],
+            terms,
+            documents: vec![],
+            document_count: 0,
+            total_length: 0,
+        }
+    }
+
+    /// Adds a document, which starts at `line_number` of the file at `path`
+    pub fn add(&mut self, path: &str, line_number: usize, text: &str) {
+        // counted the same way as `maps` in ch8_common_collections counts words
+        let mut counts = HashMap::new();
+        let mut length = 0;
+        for (_, word) in words(text) {
+            let count = counts.entry(word).or_insert(0);
+            *count += 1;
+            length += 1;
+        }
+
+        self.document_count += 1;
+        self.total_length += length;
+        let frequencies: Vec<usize> = self
+            .terms
+            .iter()
+            .map(|term| counts.get(term).copied().unwrap_or(0))
+            .collect();
+        if frequencies.iter().all(|&f| f == 0) {
+            return;
+        }
+        for (document_frequency, &f) in self.document_frequencies.iter_mut().zip(&frequencies) {
+            if f > 0 {
+                *document_frequency += 1;
+            }
+        }
+
+        // the first line with the most different words of the query
+        let mut best = (0, 0, "");
+        for (i, line) in text.lines().enumerate() {
+            let mut found: Vec<&String> = vec![];
+            for (_, word) in words(line) {
+                if let Some(term) = self.terms.iter().find(|term| **term == word) {
+                    if !found.contains(&term) {
+                        found.push(term);
+                    }
+                }
+            }
+            if found.len() > best.1 {
+                best = (i, found.len(), line);
+            }
+        }
+
+        self.documents.push(Document {
+            path: path.to_string(),
+            snippet: best.2.to_string(),
+            line_number: line_number + best.0,
+            length,
+            frequencies,
+        });
+    }
+
+    /// Returns the best `top` documents, highest score first
+    pub fn ranked(&self, top: usize) -> Vec<(f64, &Document)> {
+        let n = self.document_count as f64;
+        let average_length = self.total_length as f64 / n.max(1.0);
+        let idfs: Vec<f64> = self
+            .document_frequencies
+            .iter()
+            .map(|&df| (1.0 + (n - df as f64 + 0.5) / (df as f64 + 0.5)).ln())
+            .collect();
+
+        let mut scored: Vec<(f64, &Document)> = self
+            .documents
+            .iter()
+            .map(|document| {
+                // empty documents don't get this far, but an empty corpus has no average
+                let length = document.length as f64 / average_length.max(f64::MIN_POSITIVE);
+                let score = document
+                    .frequencies
+                    .iter()
+                    .zip(&idfs)
+                    .map(|(&tf, idf)| {
+                        let tf = tf as f64;
+                        idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length))
+                    })
+                    .sum();
+                (score, document)
+            })
+            .collect();
+
+        // a stable sort keeps ties in the order they were found
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(top);
+        scored
+    }
+
+    /// Cuts a long snippet down to the part around the first word of the query,
+    /// and finds the words of the query in it to highlight
+    pub fn snippet(&self, line: &str) -> (String, Vec<Hit>) {
+        let is_term = |word: &String| self.terms.contains(word);
+        let chars = line.chars().count();
+        let snippet = if chars <= SNIPPET_CHARS {
+            line.to_string()
+        } else {
+            let first = words(line)
+                .into_iter()
+                .find(|(_, word)| is_term(word))
+                .map_or(0, |(bytes, _)| line[..bytes.start].chars().count());
+            let start = first
+                .saturating_sub(SNIPPET_CHARS / 4)
+                .min(chars - SNIPPET_CHARS);
+            let mut cut: String = line.chars().skip(start).take(SNIPPET_CHARS).collect();
+            if start > 0 {
+                cut.insert_str(0, "...");
+            }
+            if start + SNIPPET_CHARS < chars {
+                cut.push_str("...");
+            }
+            cut
+        };
+
+        let hits = words(&snippet)
+            .into_iter()
+            .filter(|(_, word)| is_term(word))
+            .map(|(bytes, _)| Hit::new(&snippet, bytes.start, bytes.end, 0))
+            .collect();
+        (snippet, hits)
+    }
+
+    /// Adds the documents of a file. Each file is a document, unless it's split into paragraphs
+    /// or other records than lines. Binary files are skipped.
+    /// Archives are read like a search reads them, with each file in a tar archive
+    /// named `archive.tar:member/path`.
+    pub fn add_reader<'r, R: BufRead + 'r>(
+        &mut self,
+        path: &str,
+        reader: R,
+        config: &Config,
+    ) -> io::Result<()> {
+        self.add_input(path, Box::new(reader), config)
+    }
+
+    fn add_input(
+        &mut self,
+        path: &str,
+        reader: Box<dyn BufRead + '_>,
+        config: &Config,
+    ) -> io::Result<()> {
+        let (format, reader) = archive::detect(reader)?;
+        match format {
+            Some(Format::Gzip) => {
+                let gzip = BufReader::new(archive::Gzip::new(reader)?);
+                self.add_input(path, Box::new(gzip), config)
+            }
+            Some(Format::Tar) => {
+                let mut tar = archive::Tar::new(reader);
+                while let Some(name) = tar.next_file()? {
+                    let path = format!("{}:{}", path, name);
+                    self.add_input(&path, Box::new(BufReader::new(&mut tar)), config)?;
+                }
+                Ok(())
+            }
+            None => self.add_text(path, reader, config),
+        }
+    }
+
+    fn add_text<R: BufRead>(&mut self, path: &str, reader: R, config: &Config) -> io::Result<()> {
+        let mut reader = encoding::decode(reader, config.encoding)?;
+        if config.separator == Separator::Line {
+            let mut contents = vec![];
+            reader.read_to_end(&mut contents)?;
+            if !contents.contains(&0) {
+                self.add(path, 1, &encoding::lossy_line(&contents));
+            }
+            return Ok(());
+        }
+
+        let mut records = Records::new(reader, &config.separator);
+        let mut record = vec![];
+        while let Some(line_number) = records.next_record(&mut record)? {
+            if config.separator != Separator::Nul && record.contains(&0) {
+                break;
+            }
+            self.add(path, line_number, &encoding::lossy_line(&record));
+        }
+        Ok(())
+    }
+}
+
+/// Ranks the documents of every file and prints the best `top` of them, returns the exit code
+pub fn run(config: &Config, files: &[PathBuf], top: usize) -> io::Result<i32> {
+    let mut ranker = Ranker::new(&config.patterns.join(" "));
+    let mut had_error = false;
+
+    for file in files {
+        let result = if file == Path::new(STDIN) {
+            ranker.add_reader("(standard input)", io::stdin().lock(), config)
+        } else {
+            File::open(file).and_then(|opened| {
+                ranker.add_reader(&file.display().to_string(), BufReader::new(opened), config)
+            })
+        };
+        if let Err(e) = result {
+            eprintln!("{}: {}", file.display(), e);
+            had_error = true;
+        }
+    }
+
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), config, true);
+    let ranked = ranker.ranked(top);
+    for (score, document) in &ranked {
+        let (snippet, hits) = ranker.snippet(&document.snippet);
+        printer.ranked(
+            *score,
+            &document.path,
+            document.line_number,
+            snippet.as_bytes(),
+            &hits,
+        )?;
+    }
+
+    Ok(if had_error {
+        EXIT_ERROR
+    } else if ranked.is_empty() {
+        EXIT_NO_MATCH
+    } else {
+        EXIT_MATCH
+    })
+}
@@ -0,0 +1,163 @@
+// Splits input into the records that are searched, which are lines unless asked otherwise.
+// - paragraph mode: blocks of lines separated by blank lines, ex. the stanzas of a poem
+// - NUL mode: records end with a NUL byte, ex. the output of `find -print0`
+// - any other delimiter, ex. the `%` between the entries of a fortune file
+use std::io::{self, BufRead};
+
+/// What ends each record
+#[derive(Debug, Clone, PartialEq)]
+pub enum Separator {
+    Line,
+    // one or more blank lines
+    Paragraph,
+    Nul,
+    Delimiter(Vec<u8>),
+}
+
+impl Separator {
+    /// Parses a custom delimiter, where `\n`, `\t`, `\0` and `\\` are escapes
+    pub fn parse(delimiter: &str) -> Result<Separator, &'static str> {
+        let mut bytes = vec![];
+        let mut chars = delimiter.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some('\\') => '\\',
+                    _ => {
+                        return Err(
+                            "--record-separator only knows the escapes \\n, \\t, \\0 and \\\\",
+                        )
+                    }
+                },
+                c => c,
+            };
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+
+        match bytes.as_slice() {
+            [] => Err("--record-separator can't be empty"),
+            b"\n" => Ok(Separator::Line),
+            b"\0" => Ok(Separator::Nul),
+            _ => Ok(Separator::Delimiter(bytes)),
+        }
+    }
+
+    /// What's printed after each selected record
+    pub fn terminator(&self) -> &'static [u8] {
+        match self {
+            Separator::Line | Separator::Delimiter(_) => b"\n",
+            // keeps the paragraphs apart
+            Separator::Paragraph => b"\n\n",
+            // so the output can be piped to `xargs -0`
+            Separator::Nul => b"\0",
+        }
+    }
+}
+
+/// Reads one record at a time from a reader
+pub struct Records<'s, R: BufRead> {
+    reader: R,
+    separator: &'s Separator,
+    // the number of the next line to be read, counted from 1
+    line_number: usize,
+    // the line being read in paragraph mode
+    line: Vec<u8>,
+}
+
+impl<'s, R: BufRead> Records<'s, R> {
+    pub fn new(reader: R, separator: &'s Separator) -> Records<'s, R> {
+        Records::starting_at(reader, separator, 1)
+    }
+
+    /// Reads records that start partway into a file, numbering lines from `line_number`
+    pub fn starting_at(reader: R, separator: &'s Separator, line_number: usize) -> Records<'s, R> {
+        Records {
+            reader,
+            separator,
+            line_number,
+            line: vec![],
+        }
+    }
+
+    /// Reads the next record into `record`, without its separator.
+    /// Returns the line the record starts on, or `None` at the end of the input.
+    pub fn next_record(&mut self, record: &mut Vec<u8>) -> io::Result<Option<usize>> {
+        record.clear();
+        let start = self.line_number;
+
+        match self.separator {
+            Separator::Line => {
+                if self.reader.read_until(b'\n', record)? == 0 {
+                    return Ok(None);
+                }
+                self.line_number += 1;
+                // drop the line ending, like `str::lines` does
+                strip_line_ending(record);
+            }
+            Separator::Paragraph => return self.next_paragraph(record),
+            Separator::Nul => {
+                if self.reader.read_until(b'\0', record)? == 0 {
+                    return Ok(None);
+                }
+                self.line_number += count_lines(record);
+                if record.last() == Some(&b'\0') {
+                    record.pop();
+                }
+            }
+            Separator::Delimiter(delimiter) => {
+                let last = delimiter[delimiter.len() - 1];
+                while self.reader.read_until(last, record)? > 0 && !record.ends_with(delimiter) {}
+                if record.is_empty() {
+                    return Ok(None);
+                }
+                self.line_number += count_lines(record);
+                if record.ends_with(delimiter) {
+                    record.truncate(record.len() - delimiter.len());
+                }
+            }
+        }
+
+        Ok(Some(start))
+    }
+
+    // skips blank lines, then reads lines up to the next blank line
+    fn next_paragraph(&mut self, record: &mut Vec<u8>) -> io::Result<Option<usize>> {
+        let mut start = None;
+
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                break;
+            }
+            self.line_number += 1;
+
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                if start.is_some() {
+                    break;
+                }
+            } else {
+                start.get_or_insert(self.line_number - 1);
+                record.extend_from_slice(&self.line);
+            }
+        }
+
+        strip_line_ending(record);
+        Ok(start)
+    }
+}
+
+fn strip_line_ending(record: &mut Vec<u8>) {
+    if record.last() == Some(&b'\n') {
+        record.pop();
+    }
+    if record.last() == Some(&b'\r') {
+        record.pop();
+    }
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
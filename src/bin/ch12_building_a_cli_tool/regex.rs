@@ -0,0 +1,814 @@
+// A small regular expression engine for minigrep's `--regex` mode.
+// Patterns are parsed into a syntax tree, compiled into a list of instructions,
+// and then run by a Pike VM, which steps every possible thread forward one char at
+// a time. That keeps matching linear in the length of the line (no backtracking).
+//
+// Supported syntax:
+// - literals, `.`, escapes (\. \* \\ ...), \n \t \r
+// - character classes: [abc] [a-z] [^0-9] \d \D \w \W \s \S
+// - anchors: ^ $ \b \B
+// - alternation: a|b
+// - repetition: * + ? {n} {n,} {n,m} (add a trailing ? to make it lazy)
+// - groups: (abc) (?:abc)
+// With --multiline the text is a whole file, then `.` stops at newlines and `^` `$` match at the
+// start and end of each line.
+use super::lib::Boundary;
+use super::unicode;
+use std::error::Error;
+use std::fmt;
+
+/// The largest count allowed in `{n,m}`, the same as the regex crate
+const MAX_REPEAT: u32 = 1000;
+/// How many instructions a pattern may compile to. Counted repeats copy their body,
+/// so without a limit something like `(a{1000}){1000}` would take gigabytes.
+const MAX_PROGRAM: usize = 100_000;
+
+/// A compiled regular expression
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+    case_insensitive: bool,
+    multi_line: bool,
+}
+
+/// Describes why a pattern failed to parse
+#[derive(Debug, PartialEq)]
+pub struct RegexError {
+    pub message: String,
+    // char offset inside of the pattern
+    pub position: usize,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid regex at {}: {}", self.position, self.message)
+    }
+}
+
+impl Error for RegexError {}
+
+impl Regex {
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Regex, RegexError> {
+        Regex::with_boundary(pattern, case_insensitive, Boundary::Anywhere)
+    }
+
+    /// Compiles a pattern that only matches whole words or lines, like `^(?:pattern)$` for lines.
+    /// Being part of the pattern, a later alternative can still match when the first one
+    /// doesn't fit, ex. "ab" for `a|ab` in the line "ab".
+    pub fn with_boundary(
+        pattern: &str,
+        case_insensitive: bool,
+        boundary: Boundary,
+    ) -> Result<Regex, RegexError> {
+        let node = Parser::new(pattern).parse()?;
+        let node = match boundary {
+            Boundary::Anywhere => node,
+            // not next to a word char, which works for patterns that start or end with other chars
+            Boundary::Word => Node::Concat(vec![
+                Node::Assert(Assertion::NoWordBefore),
+                node,
+                Node::Assert(Assertion::NoWordAfter),
+            ]),
+            Boundary::Line => Node::Concat(vec![
+                Node::Assert(Assertion::Start),
+                node,
+                Node::Assert(Assertion::End),
+            ]),
+        };
+
+        // wrap the pattern with save instructions to record the match bounds
+        let mut compiler = Compiler { program: vec![] };
+        compiler.program.push(Inst::Save(0));
+        compiler.compile(&node)?;
+        compiler.program.push(Inst::Save(1));
+        compiler.program.push(Inst::Match);
+
+        Ok(Regex {
+            program: compiler.program,
+            case_insensitive,
+            multi_line: false,
+        })
+    }
+
+    /// Makes `.` stop at newlines, and `^` `$` match at the start and end of every line
+    pub fn multi_line(mut self, multi_line: bool) -> Regex {
+        self.multi_line = multi_line;
+        self
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    /// Returns the byte range of the leftmost match that starts at or after `start`
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            // keep trying new starting points until something matches
+            if matched.is_none() {
+                self.add_thread(&mut current, 0, pos, pos, text);
+            }
+            // an assertion like ^ or \b can fail here and still hold further on
+            if current.list.is_empty() && (matched.is_some() || pos == text.len()) {
+                break;
+            }
+
+            let c = text[pos..].chars().next();
+            let next_pos = pos + c.map_or(0, char::len_utf8);
+
+            // threads are checked in priority order
+            for i in 0..current.list.len() {
+                let (pc, thread_start) = current.list[i];
+                let step = match &self.program[pc] {
+                    Inst::Match => {
+                        // lower priority threads can't win anymore
+                        matched = Some((thread_start, pos));
+                        break;
+                    }
+                    Inst::Char(expected) => c.is_some_and(|c| self.char_eq(*expected, c)),
+                    Inst::Any => c.is_some_and(|c| !self.multi_line || c != '\n'),
+                    Inst::Class(class) => c.is_some_and(|c| self.class_matches(class, c)),
+                    // control flow is resolved by add_thread
+                    _ => false,
+                };
+
+                if step {
+                    self.add_thread(&mut next, pc + 1, thread_start, next_pos, text);
+                }
+            }
+
+            if c.is_none() {
+                break;
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            pos = next_pos;
+        }
+
+        matched
+    }
+
+    // follow jumps, splits and assertions until a char consuming instruction is reached
+    fn add_thread(&self, threads: &mut Threads, pc: usize, start: usize, pos: usize, text: &str) {
+        if threads.seen[pc] {
+            return;
+        }
+        threads.seen[pc] = true;
+
+        match &self.program[pc] {
+            Inst::Jmp(to) => self.add_thread(threads, *to, start, pos, text),
+            Inst::Split(first, second) => {
+                self.add_thread(threads, *first, start, pos, text);
+                self.add_thread(threads, *second, start, pos, text);
+            }
+            Inst::Save(0) => self.add_thread(threads, pc + 1, pos, pos, text),
+            Inst::Save(_) => self.add_thread(threads, pc + 1, start, pos, text),
+            Inst::Assert(assertion) => {
+                if assertion.holds(text, pos, self.multi_line) {
+                    self.add_thread(threads, pc + 1, start, pos, text);
+                }
+            }
+            _ => threads.list.push((pc, start)),
+        }
+    }
+
+    fn char_eq(&self, expected: char, c: char) -> bool {
+        expected == c || (self.case_insensitive && fold(expected) == fold(c))
+    }
+
+    fn class_matches(&self, class: &Class, c: char) -> bool {
+        if !self.case_insensitive {
+            return class.matches(c);
+        }
+
+        // a class matches if any casing of the char is inside of it
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let upper = c.to_uppercase().next().unwrap_or(c);
+        if class.negated {
+            class.matches(c) && class.matches(lower) && class.matches(upper)
+        } else {
+            class.matches(c) || class.matches(lower) || class.matches(upper)
+        }
+    }
+}
+
+// chars that fold into several, like 'ß' into "ss", are only compared to themselves
+fn fold(c: char) -> char {
+    let mut folded = unicode::fold(c);
+    match (folded.next(), folded.next()) {
+        (Some(folded), None) => folded,
+        _ => c,
+    }
+}
+
+/// Writes text as a pattern that only matches that text
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes text as a pattern where every run of spaces, tabs and newlines matches any other,
+/// ex. "pair of  us" matches "pair of\nus"
+pub fn flexible_whitespace(text: &str, case_insensitive: bool) -> String {
+    let mut pattern = String::new();
+    for (i, word) in text.split(char::is_whitespace).enumerate() {
+        if i > 0 && !pattern.ends_with("\\s+") {
+            pattern.push_str("\\s+");
+        }
+        if case_insensitive {
+            pattern.push_str(&folded_literal(word));
+        } else {
+            pattern.push_str(&escape(word));
+        }
+    }
+    pattern
+}
+
+// Writes text as a pattern for a case insensitive regex. The regex compares one char at a time,
+// so where a char folds into several, both ways of writing them are in the pattern.
+//   ex. "Maße" becomes "ma(?:ss|ß|ẞ)e", which also matches "MASSE"
+fn folded_literal(text: &str) -> String {
+    // the text's own chars that fold into several are there even when they aren't ASCII
+    let several: Vec<(char, String)> = unicode::FOLD_INTO_SEVERAL_ASCII
+        .chars()
+        .chain(text.chars().filter(|&c| unicode::fold(c).nth(1).is_some()))
+        .map(|c| (c, unicode::fold(c).collect()))
+        .collect();
+
+    let folded = unicode::fold_str(text);
+    let mut rest = folded.as_str();
+    let mut pattern = String::new();
+    while let Some(c) = rest.chars().next() {
+        let longest = several
+            .iter()
+            .map(|(_, spelled)| spelled)
+            .filter(|spelled| rest.starts_with(spelled.as_str()))
+            .max_by_key(|spelled| spelled.len());
+        let spelled = match longest {
+            Some(spelled) => spelled,
+            None => {
+                pattern.push_str(&escape(&c.to_string()));
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+
+        let mut chars: Vec<char> = several
+            .iter()
+            .filter(|(_, other)| other == spelled)
+            .map(|&(c, _)| c)
+            .collect();
+        chars.sort_unstable();
+        chars.dedup();
+        pattern.push_str("(?:");
+        pattern.push_str(&escape(spelled));
+        for c in chars {
+            pattern.push('|');
+            pattern.push(c);
+        }
+        pattern.push(')');
+        rest = &rest[spelled.len()..];
+    }
+    pattern
+}
+
+fn is_word_char(c: char) -> bool {
+    unicode::is_word_char(c)
+}
+
+/// Pending VM threads for a single position in the text
+struct Threads {
+    // (instruction, match start) in priority order
+    list: Vec<(usize, usize)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            list: Vec::with_capacity(size),
+            seen: vec![false; size],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        for seen in self.seen.iter_mut() {
+            *seen = false;
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    // only used by -w
+    NoWordBefore,
+    NoWordAfter,
+}
+
+impl Assertion {
+    fn holds(self, text: &str, pos: usize, multi_line: bool) -> bool {
+        let before = text[..pos].chars().next_back().is_some_and(is_word_char);
+        let after = text[pos..].chars().next().is_some_and(is_word_char);
+
+        match self {
+            Assertion::Start => pos == 0 || multi_line && text[..pos].ends_with('\n'),
+            // a line can end with "\r\n"
+            Assertion::End => {
+                let rest = &text[pos..];
+                rest.is_empty()
+                    || multi_line && (rest.starts_with('\n') || rest.starts_with("\r\n"))
+            }
+            Assertion::WordBoundary => before != after,
+            Assertion::NotWordBoundary => before == after,
+            Assertion::NoWordBefore => !before,
+            Assertion::NoWordAfter => !after,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit,
+    Word,
+    Space,
+    // a negated shorthand like \D inside of brackets
+    Not(Box<ClassItem>),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(low, high) => *low <= c && c <= *high,
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Word => is_word_char(c),
+            ClassItem::Space => c.is_whitespace(),
+            ClassItem::Not(item) => !item.matches(c),
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|item| item.matches(c)) != self.negated
+    }
+}
+
+/// Syntax tree of a parsed pattern
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(pattern: &str) -> Parser {
+        Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, RegexError> {
+        Err(RegexError {
+            message: message.to_string(),
+            position: self.pos,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(mut self) -> Result<Node, RegexError> {
+        let node = self.parse_alternation()?;
+        if self.peek().is_some() {
+            return self.error("unmatched ')'");
+        }
+        Ok(node)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.remove(0))
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.parse_repeat()?);
+        }
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+        let atom_pos = self.pos;
+        let mut node = self.parse_atom()?;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    // a lone `{` is treated as a literal
+                    None => break,
+                },
+                _ => break,
+            };
+
+            if let Node::Assert(_) | Node::Empty = node {
+                self.pos = atom_pos;
+                return self.error("nothing to repeat");
+            }
+            if let Some('*') | Some('+') | Some('?') = self.peek() {
+                self.pos += 1;
+            }
+
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+
+        Ok(node)
+    }
+
+    // parses `{n}`, `{n,}` or `{n,m}`
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let min = match self.parse_number() {
+            Some(min) => min,
+            None => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            Some(min)
+        };
+
+        if !self.eat('}') {
+            self.pos = start;
+            return Ok(None);
+        }
+        if max.is_some_and(|max| max < min) {
+            self.pos = start;
+            return self.error("repetition range is out of order");
+        }
+        if max.unwrap_or(min) > MAX_REPEAT {
+            self.pos = start;
+            return self.error(&format!("repetition count is over {}", MAX_REPEAT));
+        }
+
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        // digits past u32 are kept as u32::MAX, so the count is refused as too big
+        // rather than read as a literal `{`
+        let digits = &self.chars[start..self.pos];
+        if digits.is_empty() {
+            return None;
+        }
+        Some(digits.iter().fold(0u32, |n, c| {
+            n.saturating_mul(10)
+                .saturating_add(c.to_digit(10).unwrap_or(0))
+        }))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("unexpected end of pattern"),
+        };
+        self.pos += 1;
+
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assert(Assertion::Start)),
+            '$' => Ok(Node::Assert(Assertion::End)),
+            '[' => self.parse_class(),
+            '(' => {
+                // groups only affect precedence, so (?:...) is the same as (...)
+                if self.peek() == Some('?') {
+                    if self.chars.get(self.pos + 1) != Some(&':') {
+                        return self.error("unsupported group flag");
+                    }
+                    self.pos += 2;
+                }
+
+                let node = self.parse_alternation()?;
+                if !self.eat(')') {
+                    return self.error("missing ')'");
+                }
+                Ok(node)
+            }
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                self.error("nothing to repeat")
+            }
+            '\\' => self.parse_escape(),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, RegexError> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return self.error("trailing '\\'"),
+        };
+        self.pos += 1;
+
+        let class = |item: ClassItem, negated: bool| {
+            Ok(Node::Class(Class {
+                items: vec![item],
+                negated,
+            }))
+        };
+
+        match c {
+            'b' => Ok(Node::Assert(Assertion::WordBoundary)),
+            'B' => Ok(Node::Assert(Assertion::NotWordBoundary)),
+            'd' => class(ClassItem::Digit, false),
+            'D' => class(ClassItem::Digit, true),
+            'w' => class(ClassItem::Word, false),
+            'W' => class(ClassItem::Word, true),
+            's' => class(ClassItem::Space, false),
+            'S' => class(ClassItem::Space, true),
+            _ => self.escaped_char(c).map(Node::Char),
+        }
+    }
+
+    fn escaped_char(&self, c: char) -> Result<char, RegexError> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            c if c.is_ascii_alphanumeric() => self.error("unknown escape sequence"),
+            c => Ok(c),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let start = self.pos - 1;
+        let negated = self.eat('^');
+        let mut items = vec![];
+
+        // a `]` at the start of the class is a literal
+        if self.eat(']') {
+            items.push(ClassItem::Range(']', ']'));
+        }
+
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    self.pos = start;
+                    return self.error("missing ']'");
+                }
+            };
+            self.pos += 1;
+
+            let low = match c {
+                ']' => break,
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some(escaped) => escaped,
+                        None => continue,
+                    };
+                    self.pos += 1;
+
+                    let item = match escaped {
+                        'd' => Some(ClassItem::Digit),
+                        'D' => Some(ClassItem::Not(Box::new(ClassItem::Digit))),
+                        'w' => Some(ClassItem::Word),
+                        'W' => Some(ClassItem::Not(Box::new(ClassItem::Word))),
+                        's' => Some(ClassItem::Space),
+                        'S' => Some(ClassItem::Not(Box::new(ClassItem::Space))),
+                        _ => None,
+                    };
+                    match item {
+                        Some(item) => {
+                            items.push(item);
+                            continue;
+                        }
+                        None => self.escaped_char(escaped)?,
+                    }
+                }
+                c => c,
+            };
+
+            // `a-z` is a range, but a trailing `-` is a literal
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                items.push(ClassItem::Range(low, low));
+                continue;
+            }
+
+            self.pos += 1;
+            let mut high = self.chars[self.pos];
+            self.pos += 1;
+            if high == '\\' {
+                high = match self.peek() {
+                    Some(escaped) => {
+                        self.pos += 1;
+                        self.escaped_char(escaped)?
+                    }
+                    None => return self.error("trailing '\\'"),
+                };
+            }
+            if high < low {
+                return self.error("character range is out of order");
+            }
+            items.push(ClassItem::Range(low, high));
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        if self.program.len() > MAX_PROGRAM {
+            return Err(RegexError {
+                message: format!("pattern compiles to over {} instructions", MAX_PROGRAM),
+                position: 0,
+            });
+        }
+
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => self.program.push(Inst::Char(*c)),
+            Node::Any => self.program.push(Inst::Any),
+            Node::Class(class) => self.program.push(Inst::Class(class.clone())),
+            Node::Assert(assertion) => self.program.push(Inst::Assert(*assertion)),
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(branches) => {
+                // split to each branch, then jump past the others
+                let mut jumps = vec![];
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.placeholder();
+                        self.compile(branch)?;
+                        jumps.push(self.placeholder());
+                        let next = self.program.len();
+                        self.program[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.compile(branch)?;
+                    }
+                }
+
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+
+                match max {
+                    // x* loop: split to the body or past the jump back
+                    None => {
+                        let split = self.placeholder();
+                        self.compile(node)?;
+                        self.program.push(Inst::Jmp(split));
+                        let end = self.program.len();
+                        self.program[split] = self.split(split + 1, end, *greedy);
+                    }
+                    // each optional copy can skip to the end
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.placeholder());
+                            self.compile(node)?;
+                        }
+
+                        let end = self.program.len();
+                        for split in splits {
+                            self.program[split] = self.split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn placeholder(&mut self) -> usize {
+        self.program.push(Inst::Match);
+        self.program.len() - 1
+    }
+
+    fn split(&self, body: usize, end: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, end)
+        } else {
+            Inst::Split(end, body)
+        }
+    }
+}
@@ -0,0 +1,145 @@
+// Rewrites the matches that minigrep finds.
+// - `--replace` swaps each match for other text in the printed lines
+// - `--in-place` writes the changes back through a temporary file that's renamed over the original,
+//   so a file is never left half written
+// - `--dry-run` prints a unified diff of what `--in-place` would change instead
+use super::lib::Hit;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+/// Lines of context around each change in a diff, the same as `diff -u`
+const DIFF_CONTEXT: usize = 3;
+
+/// What to do with the files once their matches are replaced
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    // keeps a copy of the original with this suffix added, ex. `.bak`
+    InPlace { backup: Option<String> },
+    DryRun,
+}
+
+/// Returns the line with every hit swapped for the replacement
+pub fn replace_hits(line: &[u8], hits: &[Hit], replacement: &[u8]) -> Vec<u8> {
+    let mut replaced = Vec::with_capacity(line.len());
+    let mut written = 0;
+    for hit in hits {
+        replaced.extend_from_slice(&line[written..hit.bytes.start]);
+        replaced.extend_from_slice(replacement);
+        written = hit.bytes.end;
+    }
+    replaced.extend_from_slice(&line[written..]);
+    replaced
+}
+
+/// Replaces a file's contents all at once, or not at all, creating it if needed
+pub fn write_atomically(path: &Path, contents: &[u8], backup: Option<&str>) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    // next to the original, since a rename can't move a file to another file system
+    let temp = path.with_file_name(format!(
+        ".{}.minigrep-{}",
+        name.to_string_lossy(),
+        process::id()
+    ));
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(contents)?;
+        // a new file keeps the default permissions
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+
+        if let Some(suffix) = backup {
+            let mut backup = name.to_os_string();
+            backup.push(suffix);
+            fs::copy(path, path.with_file_name(backup))?;
+        }
+        fs::rename(&temp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Formats changed lines as a unified diff, which `patch -p0` can apply.
+/// `lines` are the original lines with their endings, `changes` are (index, new text)
+/// in the order of the lines they replace.
+pub fn unified_diff(path: &str, lines: &[&[u8]], changes: &[(usize, Vec<u8>)]) -> Vec<u8> {
+    let mut diff = format!("--- {}\n+++ {}\n", path, path).into_bytes();
+    // how many more lines the new file has than the old one, up to the current hunk
+    let mut offset: isize = 0;
+
+    let mut rest = changes;
+    while !rest.is_empty() {
+        // changes whose context would touch go in the same hunk
+        let mut len = 1;
+        while len < rest.len() && rest[len].0 - rest[len - 1].0 <= 2 * DIFF_CONTEXT + 1 {
+            len += 1;
+        }
+        let (hunk, after) = rest.split_at(len);
+        rest = after;
+
+        let start = hunk[0].0.saturating_sub(DIFF_CONTEXT);
+        let end = (hunk[len - 1].0 + DIFF_CONTEXT + 1).min(lines.len());
+        let mut body = vec![];
+        let mut new_count = 0;
+        let mut changed = hunk.iter().peekable();
+        for (i, &line) in lines.iter().enumerate().take(end).skip(start) {
+            match changed.next_if(|(index, _)| *index == i) {
+                Some((_, new)) => {
+                    diff_lines(&mut body, b'-', line);
+                    new_count += diff_lines(&mut body, b'+', new);
+                }
+                None => new_count += diff_lines(&mut body, b' ', line),
+            }
+        }
+
+        let old_count = end - start;
+        let new_start = (start as isize + offset) as usize;
+        offset += new_count as isize - old_count as isize;
+        diff.extend_from_slice(
+            format!(
+                "@@ -{} +{} @@\n",
+                hunk_range(start, old_count),
+                hunk_range(new_start, new_count)
+            )
+            .as_bytes(),
+        );
+        diff.append(&mut body);
+    }
+
+    diff
+}
+
+// writes each line of the text with a prefix, and returns how many lines there were
+fn diff_lines(diff: &mut Vec<u8>, prefix: u8, text: &[u8]) -> usize {
+    let mut count = 0;
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        diff.push(prefix);
+        diff.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            diff.extend_from_slice(b"\n\\ No newline at end of file\n");
+        }
+        count += 1;
+    }
+    count
+}
+
+// ex. "4,7", or only "4" for a single line. Empty ranges name the line before them.
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
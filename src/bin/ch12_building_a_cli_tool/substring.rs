@@ -0,0 +1,282 @@
+// Finds a literal query in a line, looking at each byte of the line once.
+// The two rarest bytes of the query are checked at 16 positions of the line at a time,
+// and only the chunks where both are found are compared with the whole query.
+//   ex. searching "frog" in "How public, like a frog" only compares the query at the 'f'
+//   that's followed by a 'g' 3 bytes later.
+// - ignore case searches compare ASCII letters with `b | 0x20`, which lowercases them
+// - lines where ASCII lowercasing isn't enough are case folded a piece at a time into a buffer
+//   that's reused for every line, and each piece is searched the same way
+use super::unicode;
+use std::cell::RefCell;
+
+// how many more bytes of a line are folded before searching again
+const FOLD_PIECE: usize = 256;
+
+thread_local! {
+    // the folded text of the line being searched, kept so each line doesn't allocate its own
+    static FOLDED: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A query prepared for searching many lines
+#[derive(Debug)]
+pub struct Finder {
+    // case folded when ignoring case
+    query: String,
+    pair: Pair,
+    ignore_case: bool,
+    // set when the query is ASCII and no other char can fold into part of it
+    ascii_only: bool,
+}
+
+impl Finder {
+    pub fn new(query: &str, ignore_case: bool) -> Finder {
+        let query = if ignore_case {
+            unicode::fold_str(query)
+        } else {
+            query.to_string()
+        };
+
+        Finder {
+            ascii_only: query.is_ascii()
+                && !query
+                    .chars()
+                    .any(|c| unicode::FOLDED_INTO_FROM_UNICODE.contains(c)),
+            pair: Pair::new(query.as_bytes(), ignore_case),
+            query,
+            ignore_case,
+        }
+    }
+
+    /// Returns the byte range of the first match at or after `start`
+    #[inline]
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let rest = &text[start..];
+        // an ASCII query can only match other chars if they fold into ASCII,
+        // and lines without any of those are searched as they are
+        if self.ignore_case && !self.ascii_only && !rest.is_ascii() {
+            let ascii = rest.bytes().take_while(u8::is_ascii).count();
+            return self.find_folded(text, start, start + ascii);
+        }
+        let i = self.find_bytes(rest.as_bytes())?;
+        Some((start + i, start + i + self.query.len()))
+    }
+
+    #[inline]
+    fn find_bytes(&self, haystack: &[u8]) -> Option<usize> {
+        let needle = self.query.as_bytes();
+        if needle.is_empty() {
+            return Some(0);
+        }
+
+        self.pair.find(haystack, needle.len(), |i| {
+            let window = &haystack[i..i + needle.len()];
+            if self.ignore_case {
+                window.eq_ignore_ascii_case(needle)
+            } else {
+                window == needle
+            }
+        })
+    }
+
+    // folds the line from `start` a piece at a time and searches each piece, so the rest of
+    // the line isn't folded once a match is found. `ascii` is where its first char that isn't
+    // ASCII is.
+    #[inline(never)]
+    fn find_folded(&self, line: &str, start: usize, ascii: usize) -> Option<(usize, usize)> {
+        FOLDED.with(|folded| {
+            let mut folded = folded.borrow_mut();
+            folded.clear();
+            folded.extend(
+                line.as_bytes()[start..ascii]
+                    .iter()
+                    .map(u8::to_ascii_lowercase),
+            );
+
+            let mut chars = line[ascii..].chars();
+            let mut from = 0;
+            loop {
+                let wanted = folded.len() + FOLD_PIECE;
+                let mut done = false;
+                while folded.len() < wanted && !done {
+                    match chars.next() {
+                        Some(c) => self.fold_into(c, &mut folded),
+                        None => done = true,
+                    }
+                }
+
+                while let Some(i) = self.find_bytes(&folded[from..]).map(|i| from + i) {
+                    // some chars fold into more than one char, which all have to match
+                    if let Some(range) = self.origin(line, start, i, i + self.query.len()) {
+                        return Some(range);
+                    }
+                    from = i + 1;
+                }
+                if done {
+                    return None;
+                }
+                // a match can start in the piece that was searched and end in the next one
+                from = from.max((folded.len() + 1).saturating_sub(self.query.len()));
+            }
+        })
+    }
+
+    fn fold_into(&self, c: char, folded: &mut Vec<u8>) {
+        if c.is_ascii() {
+            folded.push(c.to_ascii_lowercase() as u8);
+        } else if self.query.is_ascii() && !unicode::folds_into_ascii(c) {
+            // it can't be part of a match, so it doesn't have to be folded,
+            // and 0xff isn't in any UTF-8 text
+            folded.push(0xff);
+        } else {
+            for c in unicode::fold(c) {
+                folded.extend(c.encode_utf8(&mut [0; 4]).bytes());
+            }
+        }
+    }
+
+    // how many bytes `fold_into` adds for a char
+    fn folded_len(&self, c: char) -> usize {
+        if c.is_ascii() || self.query.is_ascii() && !unicode::folds_into_ascii(c) {
+            1
+        } else {
+            unicode::fold(c).map(char::len_utf8).sum()
+        }
+    }
+
+    // Finds the range of the line that was folded into `from..to`, or `None` if that
+    // starts or ends part way through what a char was folded into
+    fn origin(&self, line: &str, start: usize, from: usize, to: usize) -> Option<(usize, usize)> {
+        let mut folded = 0;
+        let mut range = (None, None);
+        let chars = line[start..]
+            .char_indices()
+            .map(|(i, c)| (start + i, Some(c)));
+        for (i, c) in chars.chain(Some((line.len(), None))) {
+            if folded == from {
+                range.0 = Some(i);
+            }
+            if folded == to {
+                range.1 = Some(i);
+            }
+            match c {
+                Some(c) if folded < to => folded += self.folded_len(c),
+                _ => break,
+            }
+        }
+        Some((range.0?, range.1?))
+    }
+}
+
+// bytes from the most to the least common in text, the ones that aren't here are rarer still
+const COMMON: &[u8] = b" etaoinsrhldcumfpgwybvkxjqz";
+
+// how many positions are checked at once
+const CHUNK: usize = 16;
+
+fn rarity(b: u8, ignore_case: bool) -> usize {
+    let rank = |b: u8| COMMON.iter().position(|&c| c == b);
+    match rank(b) {
+        Some(rank) => rank,
+        // in case sensitive searches capitals are less common than their lowercase
+        None if b.is_ascii_uppercase() && !ignore_case => {
+            COMMON.len() + rank(b.to_ascii_lowercase()).unwrap_or(0)
+        }
+        None => 2 * COMMON.len(),
+    }
+}
+
+/// Two bytes of the query, and where they are in it
+#[derive(Debug, Clone, Copy)]
+struct Pair {
+    offsets: [usize; 2],
+    // each byte repeated for a whole chunk, so they're compared with one at once
+    bytes: [[u8; CHUNK]; 2],
+    // 0x20 for letters when ignoring case, set on the line's bytes before comparing them
+    case_bits: [[u8; CHUNK]; 2],
+}
+
+impl Pair {
+    fn new(needle: &[u8], ignore_case: bool) -> Pair {
+        let rarest = |skip: Option<u8>| {
+            needle
+                .iter()
+                .enumerate()
+                .filter(|&(_, &b)| Some(b) != skip)
+                .max_by_key(|&(i, &b)| (rarity(b, ignore_case), std::cmp::Reverse(i)))
+                .map(|(i, _)| i)
+        };
+        let first = rarest(None).unwrap_or(0);
+        // a different byte rules out more positions, but the query can be one byte repeated
+        let second = rarest(needle.get(first).copied()).unwrap_or_else(|| {
+            if needle.len() > 1 {
+                needle.len() - 1
+            } else {
+                first
+            }
+        });
+
+        let byte = |i: usize| needle.get(i).copied().unwrap_or(0);
+        let case_bit = |i: usize| {
+            if ignore_case && byte(i).is_ascii_alphabetic() {
+                0x20
+            } else {
+                0
+            }
+        };
+        Pair {
+            offsets: [first, second],
+            bytes: [[byte(first); CHUNK], [byte(second); CHUNK]],
+            case_bits: [[case_bit(first); CHUNK], [case_bit(second); CHUNK]],
+        }
+    }
+
+    /// Returns the first place a query of `len` bytes could start that `matches` accepts
+    #[inline(always)]
+    fn find<F: Fn(usize) -> bool>(&self, haystack: &[u8], len: usize, matches: F) -> Option<usize> {
+        let end = (haystack.len() + 1).checked_sub(len)?;
+        // copied, so the compiler knows `matches` can't change it and doesn't reload it
+        let pair = *self;
+        // the bytes at each offset from the places the query could start
+        let at_offset = |k: usize| &haystack[pair.offsets[k]..][..end];
+        let (first, second) = (at_offset(0), at_offset(1));
+        let bytes = [pair.bytes[0][0], pair.bytes[1][0]];
+        let case_bits = [pair.case_bits[0][0], pair.case_bits[1][0]];
+        let fits = |b: u8, k: usize| b | case_bits[k] == bytes[k];
+        let check = |from: usize, to: usize| {
+            (from..to).find(|&i| fits(first[i], 0) && fits(second[i], 1) && matches(i))
+        };
+        if end < CHUNK {
+            return check(0, end);
+        }
+
+        let mut pos = 0;
+        let chunks = first.chunks_exact(CHUNK).zip(second.chunks_exact(CHUNK));
+        for (first, second) in chunks {
+            if pair.any_in_chunk(first, second) {
+                if let Some(i) = check(pos, pos + CHUNK) {
+                    return Some(i);
+                }
+            }
+            pos += CHUNK;
+        }
+        // the last chunk overlaps the one before it, whose places were already checked
+        let last = end - CHUNK;
+        if pos < end && pair.any_in_chunk(&first[last..], &second[last..]) {
+            return check(pos, end);
+        }
+        None
+    }
+
+    // whether both bytes of the pair are in place anywhere in a chunk of each.
+    // Written without branches, so it's compiled to compare the whole chunk at once.
+    #[inline(always)]
+    fn any_in_chunk(&self, first: &[u8], second: &[u8]) -> bool {
+        let (first, second) = (&first[..CHUNK], &second[..CHUNK]);
+        let mut found = false;
+        for i in 0..CHUNK {
+            found |= (first[i] | self.case_bits[0][i] == self.bytes[0][i])
+                & (second[i] | self.case_bits[1][i] == self.bytes[1][i]);
+        }
+        found
+    }
+}
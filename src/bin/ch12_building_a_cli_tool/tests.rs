@@ -1,6 +1,32 @@
 #[cfg(test)]
 mod tests {
-    use super::super::lib::{search, search_case_insensitive};
+    use super::super::aho_corasick::AhoCorasick;
+    use super::super::archive::{self, Format, Gzip, Tar};
+    use super::super::args::{self, ArgsError};
+    use super::super::encoding::{self, Encoding};
+    use super::super::fuzzy::Fuzzy;
+    use super::super::glob::Glob;
+    use super::super::index::{self, BuildStats, Plan};
+    use super::super::interactive::{self, Action, Browser, Key};
+    use super::super::json;
+    use super::super::lib::{
+        find_matches, search, search_case_insensitive, search_regex, Boundary, Config, Hit,
+        Matcher, Searcher, STDIN,
+    };
+    use super::super::printer::{ColorChoice, Printer};
+    use super::super::query::Query;
+    use super::super::rank::{self, Ranker};
+    use super::super::regex::{self, Regex};
+    use super::super::replace;
+    use super::super::substring::Finder;
+    use super::super::unicode::{self, Form};
+    use super::super::walk::{self, Filter, IgnoreFile};
+    use super::super::watch::{Follower, Snapshot, Watch};
+    use std::fs;
+    use std::io::{BufReader, Read};
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     #[test]
     fn case_sensitive() {
@@ -28,4 +54,1944 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
+
+    #[test]
+    fn regex_anchors_and_repetition() {
+        let contents = include_str!("../../../assets/poem.txt");
+        let regex = Regex::new("^How .*!$", false).unwrap();
+
+        assert_eq!(
+            vec!["How dreary to be somebody!"],
+            search_regex(&regex, contents)
+        );
+        // an assertion that fails at the start can still hold later
+        assert_eq!(
+            Some((1, 4)),
+            Regex::new(r"\bfoo", false).unwrap().find_at(" foo", 0)
+        );
+
+        let regex = Regex::new("^How.*$", false).unwrap().multi_line(true);
+        assert_eq!(Some((115, 141)), regex.find_at(contents, 0));
+        assert_eq!(Some((142, 165)), regex.find_at(contents, 141));
+        assert_eq!(
+            None,
+            Regex::new("a.b", false)
+                .unwrap()
+                .multi_line(true)
+                .find_at("a\nb", 0)
+        );
+    }
+
+    #[test]
+    fn regex_classes_groups_and_alternation() {
+        let regex = Regex::new(r"^(ab|cd){2,3}[0-9]+\b", false).unwrap();
+
+        assert!(regex.is_match("abcd42"));
+        assert!(regex.is_match("cdcdab7 rest"));
+        assert!(!regex.is_match("ab42"));
+        assert!(!regex.is_match("abcd42x"));
+        assert_eq!(
+            Some((2, 3)),
+            Regex::new(r"\w+?", false).unwrap().find_at("  abc", 0)
+        );
+        assert_eq!(
+            Some((2, 5)),
+            Regex::new(r"[^\s]+", false).unwrap().find_at("  abc d", 0)
+        );
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        let regex = Regex::new("^[r-t]US", true).unwrap();
+
+        assert_eq!(vec!["Rust:"], search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn regex_parse_errors() {
+        assert!(Regex::new("(abc", false).is_err());
+        assert!(Regex::new("abc)", false).is_err());
+        assert!(Regex::new("*a", false).is_err());
+        assert!(Regex::new("[a-", false).is_err());
+        assert_eq!(1, Regex::new("a{3,1}", false).unwrap_err().position);
+
+        // huge counts, or counts that multiply, are refused instead of eating memory
+        assert_eq!(1, Regex::new("a{1001}", false).unwrap_err().position);
+        assert!(Regex::new("a{4294967295}", false).is_err());
+        assert!(Regex::new("a{2,1000}", false).unwrap().is_match("aa"));
+        assert!(Regex::new("(a{1000}){1000}", false).is_err());
+        assert!(Regex::new("((a|b){1000}){100}", false).is_err());
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(Glob::new("*.rs").is_match("main.rs"));
+        assert!(!Glob::new("*.rs").is_match("src/main.rs"));
+        assert!(Glob::new("src/**/*.rs").is_match("src/main.rs"));
+        assert!(Glob::new("src/**/*.rs").is_match("src/bin/ch12/lib.rs"));
+        assert!(Glob::new("**/target").is_match("target"));
+        assert!(Glob::new("target/**").is_match("target/debug/book"));
+        assert!(Glob::new("ch?_*").is_match("ch1_hello_world"));
+        assert!(!Glob::new("ch?_*").is_match("ch12_building"));
+        assert!(Glob::new("ch[0-9][0-9]_*").is_match("ch12_building"));
+        assert!(!Glob::new("ch[0-9][0-9]_*").is_match("ch1_hello_world"));
+        assert!(Glob::new("*.[ch]").is_match("lib.h"));
+        assert!(!Glob::new("*.[!ch]").is_match("lib.h"));
+        assert!(Glob::new("*.[^ch]").is_match("lib.o"));
+        assert!(Glob::new("[]-]").is_match("-"));
+        assert!(Glob::new("[a\\]]").is_match("]"));
+        assert!(!Glob::new("a[/]b").is_match("a/b"));
+        assert!(Glob::new("[ab").is_match("[ab"));
+    }
+
+    #[test]
+    fn ignore_file_rules() {
+        let ignore = IgnoreFile::parse(
+            Path::new("root"),
+            "# build output\n/target\n*.log\n!keep.log\nbuild/\ndocs/*.md\n",
+        );
+        let ignored = |path: &str, is_dir| ignore.matched(&Path::new("root").join(path), is_dir);
+
+        assert_eq!(Some(true), ignored("target", true));
+        assert_eq!(None, ignored("src/target", true));
+        assert_eq!(Some(true), ignored("src/debug.log", false));
+        assert_eq!(Some(false), ignored("src/keep.log", false));
+        assert_eq!(Some(true), ignored("src/build", true));
+        assert_eq!(None, ignored("src/build", false));
+        assert_eq!(Some(true), ignored("docs/notes.md", false));
+        assert_eq!(None, ignored("docs/nested/notes.md", false));
+    }
+
+    #[test]
+    fn walk_skips_hidden_and_ignored_files() {
+        let root = TempDir::new("walk");
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("src/.ignore"), "skip.txt\n").unwrap();
+        for file in &[
+            "a.txt",
+            "debug.log",
+            ".secret",
+            ".hidden/b.txt",
+            "src/skip.txt",
+            "src/nested/c.txt",
+        ] {
+            fs::write(root.join(file), "text").unwrap();
+        }
+
+        let root_path = root.to_str().unwrap();
+        let files = walk::files(&[root_path], &Filter::default()).files;
+
+        assert_eq!(
+            vec![root.join("a.txt"), root.join("src/nested/c.txt")],
+            files
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_skips_folder_links() {
+        let root = TempDir::new("links");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/f.txt"), "hello").unwrap();
+        // a loop back to a parent, and a link to a file
+        std::os::unix::fs::symlink("..", root.join("a/up")).unwrap();
+        std::os::unix::fs::symlink("f.txt", root.join("a/g.txt")).unwrap();
+        // a folder that can't be read is left out, and the rest is still walked
+        use std::os::unix::fs::PermissionsExt;
+        let locked = root.join("a/locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::write(locked.join("h.txt"), "hello").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        // root can read it anyway
+        let can_lock = fs::read_dir(&locked).is_err();
+
+        let root_path = root.to_str().unwrap();
+        let walked = walk::files(&[root_path], &Filter::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut files = vec![root.join("a/f.txt"), root.join("a/g.txt")];
+        if can_lock {
+            let errors: Vec<&PathBuf> = walked.errors.iter().map(|(path, _)| path).collect();
+            assert_eq!(vec![&locked], errors);
+        } else {
+            files.insert(2, locked.join("h.txt"));
+        }
+        assert_eq!(files, walked.files);
+    }
+
+    #[test]
+    fn glob_and_type_filters() {
+        let root = TempDir::new("filter");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        for file in &[
+            "README.md",
+            "notes.txt",
+            "src/main.rs",
+            "src/lib.rs",
+            "target/debug/build.rs",
+        ] {
+            fs::write(root.join(file), "text").unwrap();
+        }
+
+        let dir = root.to_str().unwrap();
+        let files = |flags: &[&str]| -> Vec<PathBuf> {
+            let mut list = flags.to_vec();
+            list.extend(&["text", dir]);
+            let list = args(&list);
+            let config = Config::new(&list).unwrap();
+            walk::files(&config.paths, &config.filter).files
+        };
+        let rust = vec![
+            root.join("src/lib.rs"),
+            root.join("src/main.rs"),
+            root.join("target/debug/build.rs"),
+        ];
+        assert_eq!(rust, files(&["-t", "rust"]));
+        assert_eq!(rust, files(&["--glob", "*.rs"]));
+        assert_eq!(rust[..2].to_vec(), files(&["-g", "*.rs", "-g", "!target"]));
+        assert_eq!(
+            rust[..2].to_vec(),
+            files(&["-g", "*.rs", "-g", "!target/**"])
+        );
+        assert_eq!(vec![root.join("src/main.rs")], files(&["-g", "src/m*"]));
+        // the last glob that matches wins
+        assert_eq!(
+            vec![root.join("src/lib.rs"), root.join("src/main.rs")],
+            files(&["-g", "!*.rs", "-g", "src/*.rs"])
+        );
+        assert_eq!(
+            vec![root.join("README.md"), root.join("notes.txt")],
+            files(&["-T", "rust"])
+        );
+        assert_eq!(
+            vec![root.join("README.md"), root.join("notes.txt")],
+            files(&["-t", "md", "--type", "txt"])
+        );
+        assert_eq!(
+            vec![root.join("notes.txt")],
+            files(&["-g", "!README*", "-T", "rust"])
+        );
+
+        // files named on the command line are always searched
+        let list = args(&["-t", "rust", "frog", "assets/poem.txt"]);
+        let config = Config::new(&list).unwrap();
+        assert_eq!(
+            vec![PathBuf::from("assets/poem.txt")],
+            walk::files(&config.paths, &config.filter).files
+        );
+
+        assert!(matches!(
+            Config::new(&args(&["-t", "cobol", "a"])),
+            Err(ArgsError::Invalid(_))
+        ));
+        assert!(matches!(
+            Config::new(&args(&["--type-list"])),
+            Err(ArgsError::TypeList)
+        ));
+        assert!(walk::type_list().contains("\nrust: *.rs\n"));
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        let mut all = vec!["minigrep".to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        all
+    }
+
+    // a folder of its own for a test, deleted when it's dropped so a failing test doesn't leave it
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("minigrep_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn match_records_have_locations() {
+        let matcher = Matcher::Literal(Finder::new("us", false));
+        let contents = "Then there's a pair of us - don't tell!\nThey'd banish us, you know.";
+        let matches = find_matches(&matcher, contents);
+
+        assert_eq!(2, matches.len());
+        assert_eq!(1, matches[0].line_number);
+        assert_eq!(
+            vec![Hit {
+                bytes: 23..25,
+                columns: 24..26,
+                pattern: 0,
+                distance: 0
+            }],
+            matches[0].hits
+        );
+        assert_eq!(2, matches[1].line_number);
+        assert_eq!(
+            vec![Hit {
+                bytes: 14..16,
+                columns: 15..17,
+                pattern: 0,
+                distance: 0
+            }],
+            matches[1].hits
+        );
+    }
+
+    #[test]
+    fn case_insensitive_hits_point_into_the_original_line() {
+        let matcher = Matcher::Literal(Finder::new("straße", true));
+        let matches = find_matches(&matcher, "İ GROẞE STRASSE Straße");
+
+        // `İ` and `ß` fold into different lengths, but offsets still point into the line
+        assert_eq!(
+            vec![
+                Hit {
+                    bytes: 11..18,
+                    columns: 9..16,
+                    pattern: 0,
+                    distance: 0
+                },
+                Hit {
+                    bytes: 19..26,
+                    columns: 17..23,
+                    pattern: 0,
+                    distance: 0
+                }
+            ],
+            matches[0].hits
+        );
+    }
+
+    // runs a whole search over in-memory files and returns what was printed
+    fn grep<C: AsRef<[u8]>>(list: &[&str], files: &[(&str, C)]) -> (usize, String) {
+        let args = args(list);
+        let config = Config::new(&args).unwrap();
+        let searcher = Searcher::new(&config).unwrap();
+
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, &config, files.len() > 1);
+        let mut total = 0;
+        for (path, contents) in files {
+            printer.begin_file(path).unwrap();
+            let count = searcher
+                .search_reader(contents.as_ref(), &mut printer)
+                .unwrap();
+            printer.end_file(count).unwrap();
+            total += count;
+        }
+
+        (total, String::from_utf8_lossy(&out).into_owned())
+    }
+
+    const POEM: &str = include_str!("../../../assets/poem.txt");
+
+    #[test]
+    fn printer_line_numbers_columns_and_context() {
+        let expected = "\
+5-
+6:1:How dreary to be somebody!
+7:1:How public, like a frog
+8-To tell your name the livelong day
+";
+        assert_eq!(
+            expected,
+            grep(&["--column", "-C", "1", "How"], &[("poem.txt", POEM)]).1
+        );
+    }
+
+    #[test]
+    fn printer_separates_groups() {
+        let expected = "\
+poem.txt:3:Then there's a pair of us - don't tell!
+poem.txt-4-They'd banish us, you know.
+--
+poem.txt:8:To tell your name the livelong day
+poem.txt-9-To an admiring bog!
+--
+empty.txt:1:tell
+";
+        let files = [("poem.txt", POEM), ("empty.txt", "tell")];
+        assert_eq!(expected, grep(&["-n", "-A", "1", "tell"], &files).1);
+    }
+
+    #[test]
+    fn printer_highlights_exact_hits() {
+        let contents = "İ GROẞE STRASSE Straße\nno match here";
+        let expected =
+            "\x1b[32m1\x1b[0m:İ GROẞE \x1b[1;31mSTRASSE\x1b[0m \x1b[1;31mStraße\x1b[0m\n";
+
+        assert_eq!(
+            expected,
+            grep(&["--color=always", "-in", "STRAßE"], &[("-", contents)]).1
+        );
+    }
+
+    #[test]
+    fn color_choice_parsing() {
+        assert_eq!(Ok(ColorChoice::Never), ColorChoice::parse("never"));
+        assert!(ColorChoice::parse("sometimes").is_err());
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+
+        let args = args(&["--color=rainbow", "frog", "poem.txt"]);
+        assert!(Config::new(&args).is_err());
+    }
+
+    #[test]
+    fn stdin_is_searched_without_paths() {
+        let args = args(&["frog"]);
+        assert_eq!(vec![STDIN], Config::new(&args).unwrap().paths);
+    }
+
+    #[test]
+    fn streaming_context_across_files() {
+        let files = [("a", "1\n2\n3\nx\n5\nx\r\n"), ("b", "x\n")];
+        assert_eq!(
+            "a-2\na-3\na:x\na-5\na:x\n--\nb:x\n",
+            grep(&["-B", "2", "x"], &files).1
+        );
+    }
+
+    #[test]
+    fn args_short_long_and_bundled_flags() {
+        let args = args(&[
+            "-inA2",
+            "--before-context=1",
+            "--color",
+            "--",
+            "-frog-",
+            "poem.txt",
+        ]);
+        let config = Config::new(&args).unwrap();
+
+        assert!(!config.case_sensitive);
+        assert!(config.line_number);
+        assert_eq!((1, 2), (config.before_context, config.after_context));
+        assert_eq!(ColorChoice::Auto, config.color);
+        assert_eq!(vec!["-frog-"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn args_last_case_flag_wins() {
+        assert!(
+            Config::new(&args(&["-i", "-s", "frog"]))
+                .unwrap()
+                .case_sensitive
+        );
+        assert!(
+            !Config::new(&args(&["-s", "--ignore-case", "frog"]))
+                .unwrap()
+                .case_sensitive
+        );
+    }
+
+    #[test]
+    fn args_errors() {
+        let error = |list: &[&str]| Config::new(&args(list)).err().unwrap();
+
+        assert_eq!(ArgsError::Help, error(&["frog", "--help"]));
+        assert_eq!(ArgsError::Version, error(&["-V"]));
+        assert_eq!(
+            "unknown flag '--frogs', see --help for the list of flags",
+            error(&["--frogs", "frog"]).to_string()
+        );
+        assert_eq!(
+            "unknown flag '-y', see --help for the list of flags",
+            error(&["-iy", "frog"]).to_string()
+        );
+        assert_eq!(
+            "--context needs a value",
+            error(&["frog", "-C"]).to_string()
+        );
+        assert_eq!(
+            "--context needs a whole number",
+            error(&["-C", "x", "frog"]).to_string()
+        );
+        assert_eq!("not enough arguments", error(&["-n"]).to_string());
+        assert!(args::help().contains("-i, --ignore-case"));
+    }
+
+    #[test]
+    fn invert_and_max_count() {
+        let expected = "\
+I'm nobody! Who are you?
+Then there's a pair of us - don't tell!
+";
+        assert_eq!(
+            (2, expected.to_string()),
+            grep(&["-v", "-m", "2", "Are"], &[("poem.txt", POEM)])
+        );
+        assert_eq!(
+            (
+                1,
+                "3:Then there's a pair of us - don't tell!\n4-They'd banish us, you know.\n"
+                    .to_string()
+            ),
+            grep(&["-n", "-m1", "-A1", "us"], &[("poem.txt", POEM)])
+        );
+    }
+
+    #[test]
+    fn count_and_file_list_modes() {
+        let files = [("poem.txt", POEM), ("other.txt", "frogs\nand toads")];
+
+        assert_eq!("poem.txt:2\nother.txt:0\n", grep(&["-c", "How"], &files).1);
+        assert_eq!("7\n", grep(&["-vc", "nobody"], &files[..1]).1);
+        assert_eq!("poem.txt\nother.txt\n", grep(&["-l", "frog"], &files).1);
+        assert_eq!("poem.txt\n", grep(&["-L", "toad"], &files).1);
+        assert_eq!((1, String::new()), grep(&["-q", "bog"], &files));
+    }
+
+    #[test]
+    fn aho_corasick_leftmost_longest() {
+        let automaton = AhoCorasick::new(&["he", "she", "hers", "his"], false);
+
+        assert_eq!(Some((1, 4, 1)), automaton.find_at("ushers", 0));
+        assert_eq!(Some((2, 6, 2)), automaton.find_at("ushers", 2));
+        assert_eq!(Some((0, 3, 3)), automaton.find_at("his hers", 0));
+        assert_eq!(None, automaton.find_at("nothing", 0));
+    }
+
+    #[test]
+    fn aho_corasick_case_insensitive() {
+        let automaton = AhoCorasick::new(&["NOBODY", "frog", "ǅ"], true);
+
+        assert_eq!(Some((4, 10, 0)), automaton.find_at("I'm Nobody!", 0));
+        assert_eq!(Some((12, 16, 1)), automaton.find_at("How public, FROG", 0));
+        assert_eq!(Some((2, 4, 2)), automaton.find_at("a Ǆ", 0));
+    }
+
+    #[test]
+    fn multiple_patterns_report_which_matched() {
+        let expected = "\
+3:us:Then there's a pair of us - don't tell!
+4:us:They'd banish us, you know.
+9:bog:To an admiring bog!
+";
+        assert_eq!(
+            expected,
+            grep(&["-n", "-e", "us", "-e", "bog"], &[("poem.txt", POEM)]).1
+        );
+
+        let dir = TempDir::new("patterns");
+        let patterns = dir.join("patterns.txt");
+        fs::write(&patterns, "frog\nNOBODY\n").unwrap();
+        let (count, out) = grep(
+            &["-ic", "-f", patterns.to_str().unwrap()],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!((3, "3\n".to_string()), (count, out));
+    }
+
+    #[test]
+    fn json_encoding() {
+        assert_eq!(
+            r#""say \"hi\"\\n\t\u0001 ünï""#,
+            json::string("say \"hi\"\\n\t\u{1} ünï")
+        );
+        assert_eq!(r#"{"text":"frog"}"#, json::data(b"frog"));
+        assert_eq!(r#"{"bytes":"/2Zyb2c="}"#, json::data(b"\xfffrog"));
+        assert_eq!("TWFu", json::base64(b"Man"));
+        assert_eq!("TWE=", json::base64(b"Ma"));
+        assert_eq!("TQ==", json::base64(b"M"));
+    }
+
+    #[test]
+    fn json_events() {
+        let (count, out) = grep(&["--json", "-A1", "frog"], &[("poem.txt", POEM)]);
+        let lines: Vec<&str> = out.lines().collect();
+
+        assert_eq!(1, count);
+        assert_eq!(
+            vec![
+                r#"{"type":"begin","data":{"path":{"text":"poem.txt"}}}"#,
+                r#"{"type":"match","data":{"path":{"text":"poem.txt"},"lines":{"text":"How public, like a frog"},"line_number":7,"submatches":[{"match":{"text":"frog"},"start":19,"end":23,"pattern":0}]}}"#,
+                r#"{"type":"context","data":{"path":{"text":"poem.txt"},"lines":{"text":"To tell your name the livelong day"},"line_number":8,"submatches":[]}}"#,
+                r#"{"type":"end","data":{"path":{"text":"poem.txt"},"stats":{"matched_lines":1,"matches":1}}}"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn json_reports_every_match() {
+        let (count, out) = grep(&["--json", "body"], &[("poem.txt", POEM)]);
+        assert_eq!(3, count);
+        assert_eq!(3, out.matches(r#""type":"match""#).count());
+    }
+
+    #[test]
+    fn json_summary() {
+        let args = args(&["--json", "o"]);
+        let config = Config::new(&args).unwrap();
+
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, &config, false);
+        printer.begin_file("a").unwrap();
+        printer.matched_line(1, b"foo", &[]).unwrap();
+        printer.end_file(1).unwrap();
+        printer.finish(Duration::from_millis(1500)).unwrap();
+
+        let summary = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .last()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            r#"{"type":"summary","data":{"elapsed_total":{"secs":1,"nanos":500000000,"human":"1.500000s"},"stats":{"searches":1,"searches_with_match":1,"matched_lines":1,"matches":0}}}"#,
+            summary
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_keeps_byte_offsets() {
+        let line = b"caf\xe9 frog";
+        assert_eq!("caf\u{1a} frog", encoding::lossy_line(line));
+
+        // the hit is found in the decoded text but points into the raw bytes
+        let (count, out) = grep(&["--json", "frog"], &[("latin1.txt", &line[..])]);
+        assert_eq!(1, count);
+        assert!(out.contains(r#""lines":{"bytes":"Y2Fm6SBmcm9n"}"#));
+        assert!(out.contains(r#""start":5,"end":9"#));
+
+        let (_, out) = grep(&["--color=never", "frog"], &[("latin1.txt", &line[..])]);
+        assert_eq!("caf\u{fffd} frog\n", out);
+    }
+
+    #[test]
+    fn binary_files() {
+        let contents = b"frog\0\x01\x02\nmore frog\n";
+
+        let (count, out) = grep(&["frog"], &[("frog.bin", &contents[..])]);
+        assert_eq!(1, count);
+        assert_eq!("Binary file frog.bin matches\n", out);
+
+        // counts still read the whole file
+        let (count, _) = grep(&["-c", "frog"], &[("frog.bin", &contents[..])]);
+        assert_eq!(2, count);
+
+        let (count, out) = grep(&["toad"], &[("frog.bin", &contents[..])]);
+        assert_eq!((0, ""), (count, out.as_str()));
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let text = "toad\r\nfrög\r\n";
+        let le: Vec<u8> = vec![0xff, 0xfe]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        let be: Vec<u8> = vec![0xfe, 0xff]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+
+        for contents in [le, be] {
+            let (count, out) = grep(&["-n", "frög"], &[("utf16.txt", contents)]);
+            assert_eq!((1, "2:frög\n"), (count, out.as_str()));
+        }
+    }
+
+    #[test]
+    fn legacy_encodings() {
+        let contents = b"\x93quoted\x94 caf\xe9\n";
+
+        let (_, out) = grep(
+            &["--encoding", "latin1", "café"],
+            &[("a.txt", &contents[..])],
+        );
+        assert_eq!("\u{93}quoted\u{94} café\n", out);
+
+        let (_, out) = grep(
+            &["--encoding=cp1252", "“quoted”"],
+            &[("a.txt", &contents[..])],
+        );
+        assert_eq!("“quoted” café\n", out);
+
+        assert_eq!(Ok(Encoding::Utf16Le), Encoding::parse("UTF-16LE"));
+        assert!(Config::new(&args(&["--encoding", "ebcdic", "a"])).is_err());
+    }
+
+    #[test]
+    fn appended_printers_keep_file_order() {
+        let list = args(&["-C1", "frog", "a", "b"]);
+        let config = Config::new(&list).unwrap();
+        let searcher = Searcher::new(&config).unwrap();
+
+        // each file is searched into its own printer, as the worker threads do
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, &config, true);
+        for path in ["a", "b"] {
+            let mut file_printer = Printer::new(vec![], &config, true);
+            file_printer.begin_file(path).unwrap();
+            let count = searcher
+                .search_reader("toad\nfrog\n".as_bytes(), &mut file_printer)
+                .unwrap();
+            file_printer.end_file(count).unwrap();
+            printer.append(file_printer).unwrap();
+        }
+
+        assert_eq!(
+            "a-toad\na:frog\n--\nb-toad\nb:frog\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(Some(4), Config::new(&args(&["-j4", "a"])).unwrap().threads);
+        assert_eq!(None, Config::new(&args(&["-j", "0", "a"])).unwrap().threads);
+    }
+
+    #[test]
+    fn substring_finder() {
+        let finder = Finder::new("abcab", false);
+        assert_eq!(Some((5, 10)), finder.find_at("abcaxabcab", 0));
+        assert_eq!(None, finder.find_at("abcaxabcab", 6));
+        assert_eq!(Some((3, 3)), Finder::new("", false).find_at("frog", 3));
+        // lines longer than a chunk, with the query in the last one that overlaps the others
+        let line = "How dreary to be somebody! How public, like a frog";
+        assert_eq!(Some((46, 50)), Finder::new("frog", false).find_at(line, 0));
+        assert_eq!(Some((4, 10)), Finder::new("dreary", false).find_at(line, 0));
+        assert_eq!(None, Finder::new("toad", false).find_at(line, 0));
+
+        let finder = Finder::new("FrOg", true);
+        assert_eq!(Some((12, 16)), finder.find_at("How public, FROG like a", 0));
+        assert_eq!(Some((46, 50)), finder.find_at(&line.to_uppercase(), 0));
+        // non-ASCII lines are folded, then searched the same way
+        assert_eq!(Some((4, 8)), finder.find_at("ñ, frog", 0));
+        assert_eq!(Some((0, 3)), Finder::new("k", true).find_at("\u{212a}", 0));
+        assert_eq!(
+            Some((2, 7)),
+            Finder::new("FISH", true).find_at("é\u{fb01}sh", 0)
+        );
+        // "ﬁ" folds into "fi", and a match has to cover all of it
+        assert_eq!(None, Finder::new("is", true).find_at("\u{fb01}sh", 0));
+        // long lines are folded a piece at a time, and a match can span two pieces
+        let line = format!("{}FROG{}", "é".repeat(254), "é".repeat(300));
+        assert_eq!(Some((508, 512)), finder.find_at(&line, 0));
+        let line = format!("{}\u{fb01}sh", "ñ".repeat(1000));
+        assert_eq!(
+            Some((2000, 2005)),
+            Finder::new("FISH", true).find_at(&line, 0)
+        );
+    }
+
+    #[test]
+    fn full_case_folding() {
+        let contents = "\
+Die Straße ist lang.
+DIE STRASSE IST LANG.
+ΟΔΥΣΣΈΥΣ
+Οδυσσέυς
+İstanbul ılık
+ISTANBUL ILIK";
+
+        assert_eq!(
+            vec!["Die Straße ist lang.", "DIE STRASSE IST LANG."],
+            search_case_insensitive("STRAẞE", contents)
+        );
+        // final sigma and the capital sigma it comes from fold the same
+        assert_eq!(
+            vec!["ΟΔΥΣΣΈΥΣ", "Οδυσσέυς"],
+            search_case_insensitive("οδυσσέυς", contents)
+        );
+        assert_eq!(
+            vec!["İstanbul ılık", "ISTANBUL ILIK"],
+            search_case_insensitive("istanbul ilik", contents)
+        );
+    }
+
+    #[test]
+    fn multilingual_greetings() {
+        let greetings = "\
+السلام عليكم
+Dobrý den
+Hello
+שָׁלוֹם
+नमस्ते
+こんにちは
+안녕하세요
+你好
+Olá
+Здравствуйте
+Hola";
+
+        assert_eq!(
+            vec!["Dobrý den"],
+            search_case_insensitive("DOBRÝ", greetings)
+        );
+        assert_eq!(vec!["Olá"], search_case_insensitive("OLÁ", greetings));
+        assert_eq!(
+            vec!["Здравствуйте"],
+            search_case_insensitive("здравствуйте", greetings)
+        );
+        assert_eq!(vec!["你好"], search_case_insensitive("你好", greetings));
+        assert_eq!(
+            vec!["안녕하세요"],
+            search_case_insensitive("안녕", greetings)
+        );
+        assert_eq!(
+            vec!["Hello", "Hola"],
+            search_case_insensitive("h", greetings)
+        );
+    }
+
+    #[test]
+    fn chars_folding_into_ascii() {
+        for c in (0x80..=0x10ffff).filter_map(char::from_u32) {
+            let into_ascii = unicode::fold(c).all(|c| c.is_ascii());
+            assert_eq!(into_ascii, unicode::folds_into_ascii(c), "{:?}", c);
+            let several = into_ascii && unicode::fold(c).nth(1).is_some();
+            assert_eq!(
+                several,
+                unicode::FOLD_INTO_SEVERAL_ASCII.contains(c),
+                "{:?}",
+                c
+            );
+            if into_ascii {
+                assert!(unicode::fold(c).all(|c| unicode::FOLDED_INTO_FROM_UNICODE.contains(c)));
+            }
+        }
+    }
+
+    #[test]
+    fn normalization_forms() {
+        assert_eq!(
+            "Dobry\u{301} den",
+            unicode::normalize("Dobrý den", Form::Nfd)
+        );
+        assert_eq!(
+            "Dobrý den",
+            unicode::normalize("Dobry\u{301} den", Form::Nfc)
+        );
+        assert_eq!(
+            "\u{110b}\u{1161}\u{11ab}\u{1102}\u{1167}\u{11bc}",
+            unicode::normalize("안녕", Form::Nfd)
+        );
+        assert_eq!(
+            "안녕",
+            unicode::normalize(
+                "\u{110b}\u{1161}\u{11ab}\u{1102}\u{1167}\u{11bc}",
+                Form::Nfc
+            )
+        );
+        // marks are put in order of their combining class, then composed where they can be
+        assert_eq!(
+            "\u{5e9}\u{5b8}\u{5c1}",
+            unicode::normalize("\u{5e9}\u{5c1}\u{5b8}", Form::Nfd)
+        );
+        assert_eq!("\u{1ec7}", unicode::normalize("e\u{302}\u{323}", Form::Nfc));
+        assert_eq!(
+            "\u{229}\u{301}",
+            unicode::normalize("e\u{301}\u{327}", Form::Nfc)
+        );
+        assert_eq!(Ok(Form::Nfc), Form::parse("NFC"));
+        assert!(Config::new(&args(&["--normalize=nfkc", "a"])).is_err());
+    }
+
+    #[test]
+    fn normalized_search_matches_either_form() {
+        let contents = "cafe\u{301} au lait\ncafé noir\ncafe";
+
+        let (count, _) = grep(&["café"], &[("menu.txt", contents)]);
+        assert_eq!(1, count);
+
+        for form in ["--normalize=nfc", "--normalize=nfd"] {
+            let (count, out) = grep(&[form, "--color=always", "café"], &[("menu.txt", contents)]);
+            assert_eq!(2, count);
+            // the whole decomposed char is highlighted in the original line
+            assert_eq!(
+                "\x1b[1;31mcafe\u{301}\x1b[0m au lait\n\x1b[1;31mcafé\x1b[0m noir\n",
+                out
+            );
+        }
+
+        // decomposed, the accent is separate so the plain letters match too
+        let (count, _) = grep(
+            &["--normalize=nfd", "-i", "CAFE"],
+            &[("menu.txt", contents)],
+        );
+        assert_eq!(3, count);
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let (count, out) = grep(&["-n", "-w", "us"], &[("poem.txt", POEM)]);
+        assert_eq!(2, count);
+        assert_eq!(
+            "3:Then there's a pair of us - don't tell!\n4:They'd banish us, you know.\n",
+            out
+        );
+
+        // a rejected match doesn't hide a later one on the same line
+        let (count, _) = grep(&["-w", "us"], &[("a.txt", "usual us")]);
+        assert_eq!(1, count);
+
+        let (count, _) = grep(&["-x", "I'm nobody! Who are you?"], &[("poem.txt", POEM)]);
+        assert_eq!(1, count);
+        let (count, _) = grep(&["-x", "i'm NOBODY! who are you?"], &[("poem.txt", POEM)]);
+        assert_eq!(0, count);
+        let (count, _) = grep(
+            &["-x", "-i", "i'm NOBODY! who are you?"],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!(1, count);
+        let (count, _) = grep(&["-x", "-w", "nobody"], &[("poem.txt", POEM)]);
+        assert_eq!(0, count);
+        let (count, _) = grep(&["-x", "--regex", "How.*!"], &[("poem.txt", POEM)]);
+        assert_eq!(1, count);
+
+        // a later alternative of a regex can fit when the first one doesn't
+        let (count, _) = grep(&["-x", "--regex", "a|ab"], &[("a.txt", "ab")]);
+        assert_eq!(1, count);
+        let (count, out) = grep(&["-w", "--regex", "ab|abc"], &[("a.txt", "abc")]);
+        assert_eq!((1, "abc\n"), (count, out.as_str()));
+        let (count, _) = grep(&["-w", "--regex", "-e", "-y"], &[("a.txt", "x -y z")]);
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn word_boundaries_are_unicode_aware() {
+        let contents = "café\ncafés\ncafé!\nCAFÉ au lait\ncafe\u{301}\nnaïve_café";
+        let (count, out) = grep(&["-w", "café"], &[("menu.txt", contents)]);
+        assert_eq!(2, count);
+        assert_eq!("café\ncafé!\n", out);
+
+        let (count, _) = grep(&["-w", "-i", "café"], &[("menu.txt", contents)]);
+        assert_eq!(3, count);
+
+        // an accent that follows continues the word
+        let (count, _) = grep(&["-w", "cafe"], &[("menu.txt", contents)]);
+        assert_eq!(0, count);
+        let (count, _) = grep(
+            &["-w", "--normalize=nfc", "café"],
+            &[("menu.txt", contents)],
+        );
+        assert_eq!(3, count);
+
+        let (count, _) = grep(&["-w", "-i", "ß"], &[("a.txt", "STRASSE\nSS")]);
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn paragraph_records() {
+        let (count, out) = grep(&["-n", "-p", "frog"], &[("poem.txt", POEM)]);
+        assert_eq!(1, count);
+        assert_eq!(
+            "6:How dreary to be somebody!\nHow public, like a frog\nTo tell your name the livelong day\nTo an admiring bog!\n\n",
+            out
+        );
+
+        // extra blank lines and whitespace only lines don't make empty records
+        let contents = "\n\none\ntwo\r\n  \n\n\nthree\n";
+        let (count, out) = grep(&["-n", "-p", "--regex", "o$"], &[("a.txt", contents)]);
+        assert_eq!(1, count);
+        assert_eq!("3:one\ntwo\n\n", out);
+        let (count, out) = grep(&["-n", "-p", "-v", "two"], &[("a.txt", contents)]);
+        assert_eq!(1, count);
+        assert_eq!("8:three\n\n", out);
+    }
+
+    #[test]
+    fn paragraph_context_counts_records() {
+        let contents = "a\n1\n\nb\n2\n\nc\n3\n\nd\n4\n";
+        let (_, out) = grep(&["-n", "-p", "-A", "1", "a"], &[("a.txt", contents)]);
+        assert_eq!("1:a\n1\n\n4-b\n2\n\n", out);
+        // records next to each other aren't split by `--`, even though their lines aren't
+        let (_, out) = grep(
+            &["-n", "-p", "-C", "1", "--regex", "[ab]"],
+            &[("a.txt", contents)],
+        );
+        assert_eq!("1:a\n1\n\n4:b\n2\n\n7-c\n3\n\n", out);
+        let (_, out) = grep(
+            &["-n", "-p", "-B", "1", "--regex", "[ad]"],
+            &[("a.txt", contents)],
+        );
+        assert_eq!("1:a\n1\n\n--\n7-c\n3\n\n10:d\n4\n\n", out);
+    }
+
+    #[test]
+    fn null_and_custom_records() {
+        let contents = b"first\nline\0second\0\nthird line\0";
+        let (count, out) = grep(&["-n", "-z", "line"], &[("a.txt", contents)]);
+        assert_eq!(2, count);
+        // NUL separated records aren't binary, and keep their NUL in the output
+        assert_eq!("1:first\nline\x002:\nthird line\0", out);
+
+        let fortunes = "Be yourself.\n%\nNo one is you.\nThat is your power.\n%\nFrogs!\n";
+        let (count, out) = grep(
+            &["-n", "-i", "--record-separator=\\n%\\n", "YOU"],
+            &[("fortunes", fortunes)],
+        );
+        assert_eq!(2, count);
+        assert_eq!(
+            "1:Be yourself.\n3:No one is you.\nThat is your power.\n",
+            out
+        );
+        let (count, _) = grep(
+            &["-x", "--record-separator=\\n%\\n", "Frogs!\n"],
+            &[("fortunes", fortunes)],
+        );
+        assert_eq!(1, count);
+
+        assert!(Config::new(&args(&["--record-separator=", "a"])).is_err());
+        assert!(Config::new(&args(&["--record-separator=\\q", "a"])).is_err());
+    }
+
+    #[test]
+    fn replace_prints_substituted_lines() {
+        let (count, out) = grep(&["--replace", "toad", "frog"], &[("poem.txt", POEM)]);
+        assert_eq!(1, count);
+        assert_eq!("How public, like a toad\n", out);
+
+        let (_, out) = grep(
+            &["-i", "--color=always", "--replace=X", "US"],
+            &[("a.txt", "us and us")],
+        );
+        assert_eq!("\x1b[1;31mX\x1b[0m and \x1b[1;31mX\x1b[0m\n", out);
+        // empty matches are replaced too
+        let (_, out) = grep(&["--regex", "^", "--replace", "> "], &[("a.txt", "quote")]);
+        assert_eq!("> quote\n", out);
+    }
+
+    // edits a real file and returns what was printed
+    fn edit(list: &[&str], file: &Path) -> (usize, String) {
+        let args = args(list);
+        let config = Config::new(&args).unwrap();
+        let searcher = Searcher::new(&config).unwrap();
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, &config, false);
+        let count = searcher.search_file(file, &mut printer).unwrap();
+        (count, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn dry_run_prints_a_diff() {
+        let dir = TempDir::new("dry_run");
+        let file = dir.join("poem.txt");
+        fs::write(&file, POEM).unwrap();
+
+        let (count, out) = edit(&["--dry-run", "--replace", "toad", "frog"], &file);
+        let unchanged = fs::read_to_string(&file).unwrap();
+
+        assert_eq!(1, count);
+        assert_eq!(POEM, unchanged);
+        let path = file.display();
+        assert_eq!(
+            format!(
+                "--- {}\n+++ {}\n@@ -4,6 +4,6 @@\n They'd banish us, you know.\n \n How dreary to be somebody!\n-How public, like a frog\n+How public, like a toad\n To tell your name the livelong day\n To an admiring bog!\n\\ No newline at end of file\n",
+                path, path
+            ),
+            out
+        );
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        let text: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let lines: Vec<&[u8]> = text.as_bytes().split_inclusive(|&b| b == b'\n').collect();
+
+        // changes more than 6 lines apart get their own hunks
+        let diff = replace::unified_diff(
+            "f",
+            &lines,
+            &[(0, b"a\n".to_vec()), (8, b"b\nc\n".to_vec())],
+        );
+        assert_eq!(
+            "--- f\n+++ f\n@@ -1,4 +1,4 @@\n-1\n+a\n 2\n 3\n 4\n@@ -6,7 +6,8 @@\n 6\n 7\n 8\n-9\n+b\n+c\n 10\n 11\n 12\n",
+            String::from_utf8(diff).unwrap()
+        );
+        let diff =
+            replace::unified_diff("f", &lines, &[(0, b"a\n".to_vec()), (7, b"b\n".to_vec())]);
+        assert!(String::from_utf8(diff)
+            .unwrap()
+            .starts_with("--- f\n+++ f\n@@ -1,11 +1,11 @@\n"));
+
+        let diff = replace::unified_diff("f", &[b"end"], &[(0, b"END".to_vec())]);
+        assert_eq!(
+            "--- f\n+++ f\n@@ -1 +1 @@\n-end\n\\ No newline at end of file\n+END\n\\ No newline at end of file\n",
+            String::from_utf8(diff).unwrap()
+        );
+    }
+
+    #[test]
+    fn in_place_edits_with_a_backup() {
+        let dir = TempDir::new("in_place");
+        let file = dir.join("notes.txt");
+        let original = b"frog\r\nfrogs and \xff frog\nno match\nFROG";
+        fs::write(&file, &original[..]).unwrap();
+
+        let (count, out) = edit(
+            &["-iw", "--in-place=.bak", "--replace", "toad", "frog"],
+            &file,
+        );
+        let edited = fs::read(&file).unwrap();
+        let backup = fs::read(dir.join("notes.txt.bak")).unwrap();
+        let mut left = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+
+        assert_eq!(3, count);
+        assert_eq!("", out);
+        // line endings and invalid bytes are kept
+        assert_eq!(
+            &b"toad\r\nfrogs and \xff toad\nno match\ntoad"[..],
+            &edited[..]
+        );
+        assert_eq!(&original[..], &backup[..]);
+        assert_eq!(vec!["notes.txt", "notes.txt.bak"], left);
+    }
+
+    #[test]
+    fn edit_errors() {
+        let error = |list: &[&str]| Config::new(&args(list)).err().unwrap().to_string();
+        assert_eq!(
+            "--in-place and --dry-run need --replace",
+            error(&["--in-place", "a", "f"])
+        );
+        assert_eq!(
+            "--in-place and --dry-run only work on lines",
+            error(&["-p", "--dry-run", "--replace=b", "a", "f"])
+        );
+        assert_eq!(
+            "--in-place and --dry-run can't be used with -v",
+            error(&["-v", "--dry-run", "--replace=b", "a", "f"])
+        );
+        // --dry-run wins over --in-place, in any order
+        let list = args(&["--dry-run", "--in-place", "--replace=b", "a", "f"]);
+        assert_eq!(
+            Some(replace::Edit::DryRun),
+            Config::new(&list).unwrap().edit
+        );
+
+        let list = args(&["--in-place", "--replace=b", "a"]);
+        let config = Config::new(&list).unwrap();
+        let searcher = Searcher::new(&config).unwrap();
+        let mut printer = Printer::new(vec![], &config, false);
+        assert!(searcher
+            .search_file(Path::new(STDIN), &mut printer)
+            .is_err());
+    }
+
+    // the textbook edit distance table
+    fn edit_distance(a: &[char], b: &[char]) -> usize {
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for (i, ca) in a.iter().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, cb) in b.iter().enumerate() {
+                let next = (diagonal + (ca != cb) as usize)
+                    .min(row[j] + 1)
+                    .min(row[j + 1] + 1);
+                diagonal = row[j + 1];
+                row[j + 1] = next;
+            }
+        }
+        row[b.len()]
+    }
+
+    #[test]
+    fn fuzzy_agrees_with_edit_distance() {
+        use rand::{Rng, SeedableRng, XorShiftRng};
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        let random = |rng: &mut XorShiftRng, len: usize| -> String {
+            (0..len)
+                .map(|_| *rng.choose(&['a', 'b', 'c', 'é']).unwrap())
+                .collect()
+        };
+
+        for _ in 0..3000 {
+            let query_len = rng.gen_range(1, 8);
+            let query = random(&mut rng, query_len);
+            let text_len = rng.gen_range(0, 16);
+            let text = random(&mut rng, text_len);
+            let k = rng.gen_range(0, query_len);
+            let q: Vec<char> = query.chars().collect();
+            let t: Vec<char> = text.chars().collect();
+
+            // the first char a match within k edits can end on
+            let first_end = (1..=t.len())
+                .find(|&end| (0..end).any(|start| edit_distance(&q, &t[start..end]) <= k));
+
+            let found = Fuzzy::new(&query, k, false).unwrap().find_at(&text, 0);
+            assert_eq!(
+                first_end.is_some(),
+                found.is_some(),
+                "{} in {}",
+                query,
+                text
+            );
+            if let Some((begin, end, distance)) = found {
+                let span: Vec<char> = text[begin..end].chars().collect();
+                assert!(distance <= k);
+                assert_eq!(edit_distance(&q, &span), distance, "{} in {}", query, text);
+                let end_char = text[..end].chars().count();
+                assert!(end_char >= first_end.unwrap());
+                // nothing ending at the same char is closer
+                let closest = (0..end_char)
+                    .map(|start| edit_distance(&q, &t[start..end_char]))
+                    .min();
+                assert_eq!(Some(distance), closest, "{} in {}", query, text);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzzy_matches() {
+        let notes = "Meeting with Jon Smyth\nCall John Smith\nLunch with Joan\nnothing";
+        let (count, out) = grep(&["--fuzzy", "1", "john smith"], &[("notes", notes)]);
+        assert_eq!(0, count);
+        assert_eq!("", out);
+
+        let (count, out) = grep(
+            &["--fuzzy", "1", "-i", "--color=always", "john smith"],
+            &[("notes", notes)],
+        );
+        assert_eq!(1, count);
+        assert_eq!("~0:Call \x1b[1;31mJohn Smith\x1b[0m\n", out);
+
+        let (count, out) = grep(&["--fuzzy=2", "-in", "john smith"], &[("notes", notes)]);
+        assert_eq!(2, count);
+        assert_eq!("1:~2:Meeting with Jon Smyth\n2:~0:Call John Smith\n", out);
+
+        // each hit has its own distance, and the span it matched
+        let (_, out) = grep(
+            &["--fuzzy", "1", "--json", "frog"],
+            &[("a.txt", "a frg, frogs")],
+        );
+        assert!(out.contains(
+            r#""submatches":[{"match":{"text":"frg"},"start":2,"end":5,"pattern":0,"distance":1},{"match":{"text":"frog"},"start":7,"end":11,"pattern":0,"distance":0}]"#
+        ));
+
+        // folding can turn one char into several
+        let (count, _) = grep(&["--fuzzy", "1", "-i", "STRASE"], &[("a.txt", "Straße")]);
+        assert_eq!(1, count);
+        let (count, _) = grep(&["--fuzzy", "1", "-w", "frog"], &[("a.txt", "frogspawn")]);
+        assert_eq!(0, count);
+
+        assert!(Config::new(&args(&["--fuzzy", "1", "--regex", "a"])).is_err());
+        let long = "a".repeat(65);
+        let list = args(&["--fuzzy", "1", long.as_str()]);
+        assert!(Searcher::new(&Config::new(&list).unwrap()).is_err());
+    }
+
+    #[test]
+    fn boolean_queries() {
+        let (_, out) = grep(
+            &["-n", "--boolean", "nobody AND NOT frog"],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!("1:I'm nobody! Who are you?\n2:Are you nobody, too?\n", out);
+
+        let (_, out) = grep(
+            &["-n", "--boolean", "\"public, like\" OR bog"],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!("7:How public, like a frog\n9:To an admiring bog!\n", out);
+
+        // AND binds tighter than OR, and terms next to each other are ANDed
+        let (count, _) = grep(&["--boolean", "how dreary OR bog"], &[("poem.txt", POEM)]);
+        assert_eq!(1, count);
+        let (count, _) = grep(
+            &["-i", "--boolean", "how dreary OR bog"],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!(2, count);
+        let (count, _) = grep(
+            &[
+                "-i",
+                "--boolean",
+                "How AND (dreary OR frog) AND NOT NOT public",
+            ],
+            &[("poem.txt", POEM)],
+        );
+        assert_eq!(1, count);
+
+        // per record, terms can be on different lines
+        let (_, out) = grep(
+            &["-n", "-p", "--boolean", "nobody banish"],
+            &[("poem.txt", POEM)],
+        );
+        assert!(out.starts_with("1:I'm nobody!"));
+
+        // only the terms that aren't negated are highlighted
+        let (_, out) = grep(
+            &[
+                "--color=always",
+                "--boolean",
+                "frog OR NOT (nobody OR you OR tell OR How)",
+            ],
+            &[("poem.txt", POEM)],
+        );
+        // the empty line matches too, with nothing to highlight
+        assert_eq!(
+            "\nHow public, like a \x1b[1;31mfrog\x1b[0m\nTo an admiring bog!\n",
+            out
+        );
+
+        // with -w the negated terms have to be whole words too
+        let lines = "foo bar\nfoobar\nbar\nfood foo\n";
+        let (_, out) = grep(&["-w", "--boolean", "NOT foo"], &[("a.txt", lines)]);
+        assert_eq!("foobar\nbar\n", out);
+        let (_, out) = grep(&["-w", "--boolean", "bar AND NOT foo"], &[("a.txt", lines)]);
+        assert_eq!("bar\n", out);
+
+        let query = Query::new("AND \"OR\" \"say \\\"hi\\\"\"", false, Boundary::Anywhere);
+        assert!(query.is_err());
+        let query = Query::new("\"OR\" \"say \\\"hi\\\"\"", false, Boundary::Anywhere).unwrap();
+        assert!(query.is_match("OR they say \"hi\""));
+        assert!(!query.is_match("or they say hi"));
+    }
+
+    #[test]
+    fn boolean_query_errors() {
+        let column = |query: &str| {
+            Query::new(query, false, Boundary::Anywhere)
+                .unwrap_err()
+                .column
+        };
+        assert_eq!(11, column("nobody AND"));
+        assert_eq!(5, column("NOT "));
+        assert_eq!(8, column("(a OR b"));
+        assert_eq!(3, column("a ) b"));
+        assert_eq!(3, column("a \"b c"));
+        assert_eq!(1, column("\"\""));
+        assert_eq!(2, column("()"));
+        assert_eq!(1, column(""));
+
+        let error = Query::new("frog OR", false, Boundary::Anywhere).unwrap_err();
+        assert_eq!(
+            "invalid query at column 8: expected a term after OR\n  frog OR\n         ^",
+            error.to_string()
+        );
+        let error = Query::new("(a OR b", false, Boundary::Anywhere).unwrap_err();
+        assert_eq!("expected ) to close the ( at column 1", error.message);
+
+        assert!(Config::new(&args(&["--boolean", "-e", "a", "-e", "b"])).is_err());
+        assert!(Config::new(&args(&["--boolean", "--regex", "a"])).is_err());
+    }
+
+    #[test]
+    fn trigram_index() {
+        let root = TempDir::new("index");
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a.txt"), "the frog").unwrap();
+        fs::write(root.join("b.txt"), "a toad").unwrap();
+        fs::write(root.join("nested/c.txt"), "STRAẞE FROG").unwrap();
+        fs::write(root.join("nested/d.txt"), b"\xff\xfef\0r\0o\0g\0").unwrap();
+        let dir = root.to_str().unwrap();
+
+        let narrow = |list: &[&str]| -> Vec<PathBuf> {
+            let mut list = list.to_vec();
+            list.extend(&["--index", dir]);
+            let args = args(&list);
+            let config = Config::new(&args).unwrap();
+            let files = walk::files(&config.paths, &config.filter).files;
+            index::narrow(&config, files).unwrap()
+        };
+        let build = args(&["--build-index", dir]);
+        assert_eq!(Some(dir), Config::new(&build).unwrap().build_index);
+        assert!(Config::new(&args(&["--build-index", dir, "frog"])).is_err());
+        // the words of the old command are only a search
+        let search = args(&["index", "build", dir]);
+        let search = Config::new(&search).unwrap();
+        assert_eq!(
+            (vec!["index".to_string()], None),
+            (search.patterns, search.build_index)
+        );
+        let list = args(&["--index", "frog", dir]);
+        let missing = Config::new(&list).unwrap();
+        assert!(index::narrow(&missing, vec![]).is_err());
+
+        assert_eq!(
+            BuildStats {
+                files: 4,
+                read: 4,
+                skipped: 0
+            },
+            index::build(Path::new(dir)).unwrap()
+        );
+        // UTF-16 files are always searched, and case is ignored until the exact search
+        let utf16 = root.join("nested/d.txt");
+        let frogs = vec![root.join("a.txt"), root.join("nested/c.txt"), utf16.clone()];
+        assert_eq!(frogs, narrow(&["frog"]));
+        assert_eq!(frogs, narrow(&["-i", "frog"]));
+        assert_eq!(
+            vec![root.join("nested/c.txt"), utf16.clone()],
+            narrow(&["-i", "strasse"])
+        );
+        assert_eq!(4, narrow(&["-v", "frog"]).len());
+        assert_eq!(4, narrow(&["fr"]).len());
+        assert_eq!(
+            vec![root.join("b.txt"), utf16.clone()],
+            narrow(&["--boolean", "toad AND NOT frog"])
+        );
+        assert_eq!(
+            vec![root.join("a.txt"), root.join("b.txt"), utf16.clone()],
+            narrow(&["-e", "toad", "-e", "the"])
+        );
+
+        // changed and new files are searched until the index is updated
+        fs::write(root.join("b.txt"), "a toad and a frog").unwrap();
+        fs::write(root.join("e.txt"), "no match").unwrap();
+        assert_eq!(
+            vec![
+                root.join("a.txt"),
+                root.join("b.txt"),
+                root.join("e.txt"),
+                root.join("nested/c.txt"),
+                utf16.clone()
+            ],
+            narrow(&["frog"])
+        );
+        // a file that can't be read is left out, so it's searched until it can be
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("gone.txt", root.join("f.txt")).unwrap();
+        assert_eq!(
+            BuildStats {
+                files: 5,
+                read: 2,
+                skipped: if cfg!(unix) { 1 } else { 0 }
+            },
+            index::build(Path::new(dir)).unwrap()
+        );
+        #[cfg(unix)]
+        fs::remove_file(root.join("f.txt")).unwrap();
+        assert_eq!(
+            vec![
+                root.join("a.txt"),
+                root.join("b.txt"),
+                root.join("nested/c.txt"),
+                utf16
+            ],
+            narrow(&["frog"])
+        );
+
+        fs::write(root.join(index::INDEX_FILE), "minigrep index 1\n\x05").unwrap();
+        let list = args(&["--index", "frog", dir]);
+        let damaged = Config::new(&list).unwrap();
+        let error = index::narrow(&damaged, vec![]).unwrap_err().to_string();
+        // file ids past the largest u32
+        fs::write(
+            root.join(index::INDEX_FILE),
+            b"minigrep index 1\n\x00\x01\x01\x02\xff\xff\xff\xff\x0f\x01",
+        )
+        .unwrap();
+        let overflow = index::narrow(&damaged, vec![]).unwrap_err().to_string();
+        assert!(overflow.contains("the index is damaged"));
+        assert!(error.contains("the index is damaged"));
+    }
+
+    #[test]
+    fn index_plans() {
+        let plan = |list: &[&str]| index::plan(&Config::new(&args(list)).unwrap()).unwrap();
+        let trigrams = |text: &str| match index::literal(text) {
+            Plan::Trigrams(trigrams) => trigrams,
+            Plan::All => vec![],
+            _ => unreachable!(),
+        };
+
+        assert_eq!(trigrams("fro"), trigrams("FRO"));
+        assert_eq!(2, trigrams("frog").len());
+        assert_eq!(Plan::All, index::literal("fr"));
+        assert_eq!(Plan::All, plan(&["--regex", "frog"]));
+        // each piece of a fuzzy query could be the part without a mistake
+        assert_eq!(
+            Plan::Or(vec![Plan::All, Plan::Trigrams(trigrams("cde"))]),
+            plan(&["--fuzzy", "1", "abcde"])
+        );
+        assert_eq!(
+            Plan::Or(vec![
+                Plan::Trigrams(trigrams("abc")),
+                Plan::Trigrams(trigrams("defg"))
+            ]),
+            plan(&["--fuzzy", "1", "abcdefg"])
+        );
+        assert_eq!(
+            Plan::Or(vec![
+                Plan::And(vec![Plan::Trigrams(trigrams("frog")), Plan::All]),
+                Plan::Trigrams(trigrams("bog"))
+            ]),
+            plan(&["--boolean", "frog NOT toad OR bog"])
+        );
+        assert_eq!(
+            Plan::And(vec![Plan::Trigrams(trigrams("tell!")), Plan::All]),
+            plan(&["--flex-space", "tell!  Th"])
+        );
+    }
+
+    #[test]
+    fn bm25_ranking() {
+        // rarer words count for more, and more of a word has diminishing returns
+        let mut ranker = Ranker::new("c");
+        ranker.add("a", 1, "a b");
+        ranker.add("b", 1, "c c");
+        let ranked = ranker.ranked(10);
+        assert_eq!(1, ranked.len());
+        let idf = (1.0f64 + 1.5 / 1.5).ln();
+        assert!((idf * 2.0 * 2.2 / (2.0 + 1.2) - ranked[0].0).abs() < 1e-9);
+
+        // each paragraph is a document, and its snippet is the line with the most words of the query
+        let list = args(&["--rank", "-p", "Nobody public FROG"]);
+        let config = Config::new(&list).unwrap();
+        assert_eq!(Some(10), config.rank);
+        let mut ranker = Ranker::new(&config.patterns[0]);
+        ranker
+            .add_reader("poem.txt", POEM.as_bytes(), &config)
+            .unwrap();
+        let found: Vec<(usize, &str)> = ranker
+            .ranked(10)
+            .iter()
+            .map(|(_, document)| (document.line_number, document.snippet.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (7, "How public, like a frog"),
+                (1, "I'm nobody! Who are you?")
+            ],
+            found
+        );
+        assert_eq!(1, ranker.ranked(1).len());
+
+        // without -p the whole file is one document
+        let list = args(&["--rank=3", "nobody"]);
+        let config = Config::new(&list).unwrap();
+        assert_eq!(Some(3), config.rank);
+        let mut ranker = Ranker::new("nobody");
+        ranker
+            .add_reader("poem.txt", POEM.as_bytes(), &config)
+            .unwrap();
+        ranker
+            .add_reader("other.txt", &b"somebody"[..], &config)
+            .unwrap();
+        assert_eq!(1, ranker.ranked(10).len());
+
+        // archives are ranked like they're searched, not skipped as binary files
+        let list = args(&["--rank", "disk full"]);
+        let config = Config::new(&list).unwrap();
+        let mut archives = Ranker::new("disk full");
+        for file in ["app.log.gz", "logs.tar.gz"].iter() {
+            let path = format!("assets/archives/{}", file);
+            let reader = BufReader::new(fs::File::open(&path).unwrap());
+            archives.add_reader(&path, reader, &config).unwrap();
+        }
+        let mut paths: Vec<&str> = archives
+            .ranked(10)
+            .iter()
+            .map(|(_, document)| document.path.as_str())
+            .collect();
+        paths.sort_unstable();
+        assert_eq!(
+            vec![
+                "assets/archives/app.log.gz",
+                "assets/archives/logs.tar.gz:logs/app.log"
+            ],
+            paths
+        );
+
+        let mut out = vec![];
+        let mut printer = Printer::new(&mut out, &config, false);
+        let (snippet, hits) = ranker.snippet("I'm nobody! Who are you?");
+        printer
+            .ranked(1.5, "poem.txt", 1, snippet.as_bytes(), &hits)
+            .unwrap();
+        assert_eq!(
+            "1.500 poem.txt:1:I'm nobody! Who are you?\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        // long lines are cut down to the part around the query
+        let long = format!("{}nobody{}", "a ".repeat(100), " b".repeat(100));
+        let (snippet, hits) = ranker.snippet(&long);
+        assert!(snippet.starts_with("...") && snippet.ends_with("..."));
+        assert_eq!(1, hits.len());
+        assert_eq!("nobody", &snippet[hits[0].bytes.clone()]);
+
+        assert!(Config::new(&args(&["--rank", "--fuzzy", "1", "a"])).is_err());
+        assert!(Config::new(&args(&["--rank", "-c", "a"])).is_err());
+        assert!(Config::new(&args(&["--rank", "--index", "a"])).is_err());
+        assert!(Config::new(&args(&["--rank=x", "a"])).is_err());
+        assert_eq!(
+            vec!["it", "s", "strasse", "frog"],
+            rank::words("It's STRAẞE-frog")
+                .into_iter()
+                .map(|(_, word)| word)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn follow_appended_lines() {
+        let dir = TempDir::new("follow");
+        let log = dir.join("app.log");
+        fs::write(&log, "ERROR one\nok\n").unwrap();
+
+        let list = args(&["--follow", "-n", "ERROR", log.to_str().unwrap()]);
+        let config = Config::new(&list).unwrap();
+        assert_eq!(Some(Watch::Follow), config.watch);
+        let searcher = Searcher::new(&config).unwrap();
+        let mut follower = Follower::new();
+        let mut poll = || {
+            let mut out = vec![];
+            let mut printer = Printer::new(&mut out, &config, false);
+            follower.poll(&config, &searcher, &mut printer).unwrap();
+            drop(printer);
+            String::from_utf8(out).unwrap()
+        };
+        let append = |text: &str| {
+            use std::io::Write;
+            let mut file = fs::OpenOptions::new().append(true).open(&log).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+
+        assert_eq!("1:ERROR one\n", poll());
+        assert_eq!("", poll());
+        // a line that's still being written waits for its newline
+        append("ERROR tw");
+        assert_eq!("", poll());
+        append("o\nERROR three\n");
+        assert_eq!("3:ERROR two\n4:ERROR three\n", poll());
+        // records longer than what's read at a time, and only the last one waits
+        append(&format!("{}\nERROR big", "x".repeat(20_000)));
+        assert_eq!("", poll());
+        append(&format!(" {}\n", "y".repeat(20_000)));
+        assert_eq!(format!("6:ERROR big {}\n", "y".repeat(20_000)), poll());
+
+        // truncated and rotated files are read from the start
+        fs::write(&log, "ERROR new\n").unwrap();
+        assert_eq!("1:ERROR new\n", poll());
+        fs::rename(&log, dir.join("app.log.1")).unwrap();
+        assert_eq!("", poll());
+        fs::write(&log, "x\nERROR rotated\n").unwrap();
+        assert_eq!("2:ERROR rotated\n", poll());
+
+        // the encoding of the start of a file is kept for what's appended, and -m and the
+        // context lines count the whole file
+        // prints what each poll found after appending each of `appended` in turn
+        let follow = |list: &[&str], path: &Path, appended: &[&[u8]]| -> Vec<String> {
+            let list = args(&[&["--follow"], list, &[path.to_str().unwrap()]].concat());
+            let config = Config::new(&list).unwrap();
+            let searcher = Searcher::new(&config).unwrap();
+            let mut follower = Follower::new();
+            let mut outputs = vec![];
+            for bytes in appended {
+                use std::io::Write;
+                let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+                file.write_all(bytes).unwrap();
+                let mut out = vec![];
+                let mut printer = Printer::new(&mut out, &config, false);
+                follower.poll(&config, &searcher, &mut printer).unwrap();
+                drop(printer);
+                outputs.push(String::from_utf8(out).unwrap());
+            }
+            outputs
+        };
+        let utf16 =
+            |text: &str| -> Vec<u8> { text.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+        let path = dir.join("u.txt");
+        fs::write(&path, [&[0xff, 0xfe][..], &utf16("hi\n")].concat()).unwrap();
+        // a char that's only partly written waits for the rest of it
+        let frog = utf16("ho 🐸\n");
+        let (start, rest) = frog.split_at(frog.len() - 5);
+        assert_eq!(
+            vec!["hi\n", "", "ho 🐸\n"],
+            follow(&["h"], &path, &[b"", start, rest])
+        );
+
+        let path = dir.join("m.txt");
+        fs::write(&path, "a\nERROR 1\nb\n").unwrap();
+        let appended: &[&[u8]] = &[b"", b"c\n", b"ERROR 2\n", b"ERROR 3\n"];
+        assert_eq!(
+            vec!["a\nERROR 1\n", "", "c\nERROR 2\n", ""],
+            follow(&["-m", "2", "-B", "1", "ERROR"], &path, appended)
+        );
+
+        let list = args(&["--watch", "frog", "./assets"]);
+        let config = Config::new(&list).unwrap();
+        let snapshot = || Snapshot::take(&config).unwrap();
+        assert_eq!(snapshot(), snapshot());
+        assert!(Config::new(&args(&["--watch", "a"])).is_err());
+        assert!(Config::new(&args(&["--follow", "-c", "a", "b"])).is_err());
+        assert!(Config::new(&args(&[
+            "--watch",
+            "--in-place",
+            "--replace",
+            "b",
+            "a",
+            "c"
+        ]))
+        .is_err());
+        let list = args(&["--follow", "--watch", "a", "b"]);
+        assert_eq!(Some(Watch::Follow), Config::new(&list).unwrap().watch);
+    }
+
+    #[test]
+    fn interactive_browser() {
+        assert_eq!(
+            vec![
+                Key::Char('f'),
+                Key::Char('é'),
+                Key::Up,
+                Key::PageDown,
+                Key::Backspace,
+                Key::Enter,
+                Key::Escape
+            ],
+            interactive::keys("fé\x1b[A\x1b[6~\x1b[1;5C\x7f\r\x1b".as_bytes())
+        );
+
+        let lines = POEM.lines().map(String::from).collect();
+        let list = args(&["--interactive", "-i", "fr", "poem.txt"]);
+        let mut config = Config::new(&list).unwrap();
+        let mut browser = Browser::new(vec![("poem.txt".to_string(), lines)], "fr");
+        browser.search(&mut config);
+        assert_eq!(1, browser.found.len());
+
+        // editing the query searches again
+        let mut type_keys = |browser: &mut Browser, keys: &str| {
+            interactive::keys(keys.as_bytes())
+                .into_iter()
+                .map(|key| browser.press(key, 10, &mut config))
+                .last()
+        };
+        type_keys(&mut browser, "\x7f\x7fo");
+        assert_eq!(8, browser.found.len());
+        type_keys(&mut browser, "\x1b[B\x1b[B\x1b[6~");
+        assert_eq!(7, browser.selected);
+        assert_eq!(
+            Some(Action::Open("poem.txt".to_string(), 9)),
+            type_keys(&mut browser, "\r")
+        );
+        assert_eq!(Some(Action::Quit), type_keys(&mut browser, "\x03"));
+
+        let mut screen = vec![];
+        browser.draw(&mut screen, 12, 40).unwrap();
+        let screen = String::from_utf8(screen).unwrap();
+        assert!(screen.contains("> o"));
+        assert!(screen.contains("8 matches"));
+        // the selected line is in the list and in the middle of the preview
+        assert!(screen.contains("poem.txt:9:"));
+        assert!(screen.contains("\x1b[7m     9 T\x1b[1;31mo\x1b[22;39m an"));
+
+        // wide chars take 2 columns, and one that doesn't fit isn't split
+        assert_eq!(2, unicode::width('漢'));
+        assert_eq!(2, unicode::width('🐸'));
+        assert_eq!(0, unicode::width('\u{301}'));
+        assert_eq!(8, unicode::str_width("e\u{301}漢字 🐸"));
+        let lines = vec!["漢字漢字 frog".to_string()];
+        let mut browser = Browser::new(vec![("w:".to_string(), lines)], "frog");
+        browser.search(&mut config);
+        let mut screen = vec![];
+        browser.draw(&mut screen, 12, 10).unwrap();
+        let screen = String::from_utf8(screen).unwrap();
+        assert!(screen.contains("\x1b[7mw::1:漢字 \x1b[0m"));
+        assert!(screen.contains("\x1b[7m     1 漢 \x1b[0m"));
+
+        assert!(Config::new(&args(&["--interactive", "a"])).is_err());
+        assert!(Config::new(&args(&["--interactive", "-c", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn gzip_and_tar_archives() {
+        // fixtures made with gzip -9 -n, and GNU tar in its gnu and pax formats
+        let search = |list: &[&str]| {
+            let args = args(list);
+            let config = Config::new(&args).unwrap();
+            let searcher = Searcher::new(&config).unwrap();
+            let mut out = vec![];
+            let mut printer = Printer::new(&mut out, &config, config.with_filename());
+            let file = Path::new(list[list.len() - 1]);
+            let result = searcher.search_file(file, &mut printer);
+            result.map(|count| (count, String::from_utf8(out).unwrap()))
+        };
+
+        // a single file keeps its usual output, members of a tar archive are always named
+        assert_eq!(
+            (1, "2:ERROR disk full\n".to_string()),
+            search(&["-n", "ERROR", "assets/archives/app.log.gz"]).unwrap()
+        );
+        let long = "logs/2024/a-folder-with-a-rather-long-name-so-that-the-path-no-longer-fits/\
+            in-the-hundred-bytes-of-a-tar-header/old.log";
+        for file in [
+            "assets/archives/logs.tar.gz",
+            "assets/archives/logs-pax.tar",
+        ]
+        .iter()
+        {
+            let expected = format!(
+                "{0}:{1}:2:ERROR timeout\n{0}:logs/app.log:2:ERROR disk full\n",
+                file, long
+            );
+            assert_eq!((2, expected), search(&["-n", "ERROR", file]).unwrap());
+            let expected = format!("{}:{}:1\n{}:logs/app.log:3\n", file, long, file);
+            assert_eq!((4, expected), search(&["-c", "O", file]).unwrap());
+        }
+
+        // dynamic Huffman codes with copies reaching back across reads
+        let numbers: String = (1..=2000)
+            .map(|i| format!("request {} took {}ms\n", i, i * 7919 % 1000))
+            .chain(std::iter::once("ERROR request 2001 failed\n".to_string()))
+            .collect();
+        let mut decompressed = String::new();
+        Gzip::new(&fs::read("assets/archives/numbers.log.gz").unwrap()[..])
+            .unwrap()
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(numbers, decompressed);
+
+        // stored blocks, and members one after the other like `cat a.gz b.gz`
+        let member = |text: &[u8]| {
+            let mut gz = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+            let len = text.len() as u16;
+            gz.push(1);
+            gz.extend_from_slice(&len.to_le_bytes());
+            gz.extend_from_slice(&(!len).to_le_bytes());
+            gz.extend_from_slice(text);
+            gz.extend_from_slice(&archive::crc32(0, text).to_le_bytes());
+            gz.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            gz
+        };
+        let mut gz = member(b"first\n");
+        gz.extend(member(b"second\n"));
+        let mut decompressed = String::new();
+        Gzip::new(&gz[..])
+            .unwrap()
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!("first\nsecond\n", decompressed);
+
+        // damaged archives are errors, not empty files
+        let mut bad = member(b"first\n");
+        let crc = bad.len() - 8;
+        bad[crc] ^= 1;
+        assert!(Gzip::new(&bad[..])
+            .unwrap()
+            .read_to_end(&mut vec![])
+            .is_err());
+        let cut = &gz[..gz.len() - 3];
+        assert!(Gzip::new(cut).unwrap().read_to_end(&mut vec![]).is_err());
+        let mut tar = fs::read("assets/archives/logs-pax.tar").unwrap();
+        tar[0] ^= 1;
+        assert!(Tar::new(&tar[..]).next_file().is_err());
+        // a size in binary that doesn't fit in 64 bits
+        let mut header = [0; 512];
+        header[0] = b'a';
+        header[124] = 0x80;
+        header[125..136].copy_from_slice(&[0xff; 11]);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        let sum: u32 = header.iter().map(|&b| u32::from(b)).sum::<u32>() + 8 * u32::from(b' ');
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        assert!(Tar::new(&header[..]).next_file().is_err());
+
+        // pipes can hand over a few bytes at a time, and what was read to find the format
+        // is read again after it
+        tar[0] ^= 1;
+        let (format, mut reader) = archive::detect(BufReader::with_capacity(1, &tar[..])).unwrap();
+        assert_eq!(Some(Format::Tar), format);
+        let mut read = vec![];
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(tar, read);
+        let (format, _) = archive::detect(BufReader::with_capacity(1, POEM.as_bytes())).unwrap();
+        assert_eq!(None, format);
+
+        assert_eq!(Some(Format::Gzip), archive::format(&gz));
+        assert_eq!(None, archive::format(POEM.as_bytes()));
+        assert_eq!(0xcbf4_3926, archive::crc32(0, b"123456789"));
+    }
+
+    #[test]
+    fn multiline_matches() {
+        // a phrase broken across lines is only found with -U
+        let phrase = "pair of us - don't tell!\nThey'd";
+        assert_eq!(0, grep(&["--regex", phrase], &[("poem", POEM)]).0);
+        assert_eq!(
+            (
+                2,
+                "3:Then there's a pair of us - don't tell!\n4:They'd banish us, you know.\n"
+                    .to_string()
+            ),
+            grep(&["-U", "-n", "--regex", phrase], &[("poem", POEM)])
+        );
+        // each line only highlights its own part of the match
+        let (_, out) = grep(
+            &["-U", "--color=always", "--flex-space", "tell! They'd"],
+            &[("poem", POEM)],
+        );
+        assert_eq!(
+            "Then there's a pair of us - don't \x1b[1;31mtell!\x1b[0m\n\x1b[1;31mThey'd\x1b[0m banish us, you know.\n",
+            out
+        );
+
+        // any run of whitespace in the query matches any other
+        assert_eq!(
+            regex::flexible_whitespace(" a.b  c\t", false),
+            r"\s+a\.b\s+c\s+"
+        );
+        // ignoring case, chars that fold into several are written both ways
+        assert_eq!(regex::flexible_whitespace("Maße", true), "ma(?:ss|ß|ẞ)e");
+        let streets = [("a.txt", "STRASSE\nstraße\nStrasse\nStraẞe\nstrase")];
+        assert_eq!(4, grep(&["-i", "--flex-space", "straße"], &streets).0);
+        assert_eq!(4, grep(&["-i", "--flex-space", "STRASSE"], &streets).0);
+        assert_eq!(
+            0,
+            grep(&["--flex-space", "tell! They'd"], &[("poem", POEM)]).0
+        );
+        assert_eq!(
+            1,
+            grep(&["--flex-space", "How   dreary"], &[("poem", POEM)]).0
+        );
+        assert_eq!(
+            2,
+            grep(&["-U", "--flex-space", "day To an"], &[("poem", POEM)]).0
+        );
+
+        // ^ and $ are the start and end of each line, a match ending with a newline stays on its line
+        let (count, out) = grep(&["-U", "-n", "--regex", "frog$\n"], &[("poem", POEM)]);
+        assert_eq!((1, "7:How public, like a frog\n".to_string()), (count, out));
+        let (count, out) = grep(
+            &["-U", "-v", "-m", "2", "--regex", "^(I|Are|Then).*\n"],
+            &[("poem", POEM)],
+        );
+        assert_eq!(
+            (2, "They'd banish us, you know.\n\n".to_string()),
+            (count, out)
+        );
+
+        assert!(Config::new(&args(&["-U", "--boolean", "a"])).is_err());
+        assert!(Config::new(&args(&["-U", "-p", "a"])).is_err());
+        assert!(Config::new(&args(&["-U", "--rank", "a"])).is_err());
+        assert!(Config::new(&args(&["--flex-space", "--regex", "a"])).is_err());
+    }
 }
@@ -0,0 +1,268 @@
+// Unicode case folding and normalization for matching text that's written in different ways.
+// - case folding maps every casing of a char to one form, ex. "ß", "SS" and "ss" all fold to "ss"
+// - normalization picks one way to write accents, since "é" can be one char (NFC),
+//   or "e" followed by a combining accent (NFD)
+// It also knows how many columns a char takes in a terminal, for the interactive browser.
+use super::unicode_tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS, WIDTHS};
+use std::ops::Range;
+
+/// Checks if a char outside of ASCII folds into only ASCII, ex. 'K' (Kelvin sign) into "k"
+/// and 'ﬁ' into "fi". Text without these can be folded one byte at a time.
+pub fn folds_into_ascii(c: char) -> bool {
+    matches!(
+        c,
+        'ß' | 'ẞ' | 'İ' | 'ı' | 'ſ' | '\u{212a}' | '\u{fb00}'..='\u{fb06}'
+    )
+}
+
+/// The ASCII chars that the chars above fold into
+pub const FOLDED_INTO_FROM_UNICODE: &str = "fiklst";
+
+/// The chars above that fold into more than one char, ex. 'ß' into "ss"
+pub const FOLD_INTO_SEVERAL_ASCII: &str =
+    "ßẞ\u{fb00}\u{fb01}\u{fb02}\u{fb03}\u{fb04}\u{fb05}\u{fb06}";
+
+/// Folds the case of a char, which may turn it into more than one char.
+/// Lowercasing the uppercase of a char gives the same result as Unicode's full case folding,
+/// ex. "ς" and "Σ" both become "σ", except for the two special cases below.
+pub fn fold(c: char) -> impl Iterator<Item = char> {
+    let c = match c {
+        // capital sharp s uppercases to itself, so it needs help to get to "ss"
+        'ẞ' => 'ß',
+        // Turkish dotted and dotless i are searched for as a plain i, so "İstanbul" and
+        // "istanbul" match, and so do "ılık" and "ILIK"
+        'İ' => 'I',
+        c => c,
+    };
+    c.to_uppercase().flat_map(char::to_lowercase)
+}
+
+pub fn fold_str(text: &str) -> String {
+    text.chars().flat_map(fold).collect()
+}
+
+/// Checks if a char is part of a word: letters, digits, `_` and the marks that combine with them
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || combining_class(c) != 0
+}
+
+/// How many columns a char takes in a terminal, ex. 2 for '漢' and '🐸', and 0 for a
+/// combining accent
+pub fn width(c: char) -> usize {
+    if c < '\u{300}' {
+        return 1;
+    }
+
+    let i = WIDTHS.partition_point(|&(_, last, _)| last < c);
+    match WIDTHS.get(i) {
+        Some(&(first, _, width)) if first <= c => width as usize,
+        _ => 1,
+    }
+}
+
+/// How many columns text takes in a terminal
+pub fn str_width(text: &str) -> usize {
+    text.chars().map(width).sum()
+}
+
+/// A Unicode normalization form
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Form {
+    // composed, as few chars as possible
+    Nfc,
+    // decomposed, base letters followed by their accents
+    Nfd,
+}
+
+impl Form {
+    pub fn parse(name: &str) -> Result<Form, &'static str> {
+        match name.to_lowercase().as_str() {
+            "nfc" => Ok(Form::Nfc),
+            "nfd" => Ok(Form::Nfd),
+            _ => Err("--normalize must be nfc or nfd"),
+        }
+    }
+}
+
+pub fn normalize(text: &str, form: Form) -> String {
+    Normalized::new(text, form).text
+}
+
+/// Normalized text that remembers where each of its chars came from,
+/// so a match in it can be traced back to the original text.
+pub struct Normalized {
+    pub text: String,
+    // the byte offset of each char in `text`, and the bytes of the original it came from
+    sources: Vec<(usize, Range<usize>)>,
+    original_len: usize,
+}
+
+impl Normalized {
+    pub fn new(original: &str, form: Form) -> Normalized {
+        let mut chars = decompose(original);
+        if form == Form::Nfc {
+            chars = compose(chars);
+        }
+
+        let mut text = String::with_capacity(original.len());
+        let mut sources = Vec::with_capacity(chars.len());
+        for (c, source) in chars {
+            sources.push((text.len(), source));
+            text.push(c);
+        }
+
+        Normalized {
+            text,
+            sources,
+            original_len: original.len(),
+        }
+    }
+
+    /// Returns the bytes of the original text that a range of the normalized text came from
+    pub fn source(&self, range: Range<usize>) -> Range<usize> {
+        let first = self
+            .sources
+            .partition_point(|(offset, _)| *offset < range.start);
+        let mut covered = self.sources[first..]
+            .iter()
+            .take_while(|(offset, _)| *offset < range.end)
+            .map(|(_, source)| source);
+
+        match covered.next() {
+            Some(source) => covered.fold(source.clone(), |all, source| {
+                all.start.min(source.start)..all.end.max(source.end)
+            }),
+            // an empty range stays empty, at the start of the next char
+            None => {
+                let start = self
+                    .sources
+                    .get(first)
+                    .map_or(self.original_len, |(_, source)| source.start);
+                start..start
+            }
+        }
+    }
+}
+
+// Hangul syllables are composed of 2 or 3 jamo, by formula
+const HANGUL_SYLLABLES: u32 = 0xac00;
+const HANGUL_LEADS: u32 = 0x1100;
+const HANGUL_VOWELS: u32 = 0x1161;
+const HANGUL_TRAILS: u32 = 0x11a7;
+const LEAD_COUNT: u32 = 19;
+const VOWEL_COUNT: u32 = 21;
+const TRAIL_COUNT: u32 = 28;
+const SYLLABLE_COUNT: u32 = LEAD_COUNT * VOWEL_COUNT * TRAIL_COUNT;
+
+// Splits every char into its canonical decomposition, then puts the combining marks
+// after each base char in order of their combining class
+fn decompose(text: &str) -> Vec<(char, Range<usize>)> {
+    let mut chars: Vec<(char, Range<usize>)> = Vec::with_capacity(text.len());
+
+    for (i, c) in text.char_indices() {
+        let source = i..i + c.len_utf8();
+        let mut push = |c: char| {
+            // bubble a mark back past the marks with a higher class
+            let class = combining_class(c);
+            let mut at = chars.len();
+            while class != 0 && at > 0 && combining_class(chars[at - 1].0) > class {
+                at -= 1;
+            }
+            chars.insert(at, (c, source.clone()));
+        };
+
+        let syllable = (c as u32).wrapping_sub(HANGUL_SYLLABLES);
+        if syllable < SYLLABLE_COUNT {
+            let trail = syllable % TRAIL_COUNT;
+            push(char_from(
+                HANGUL_LEADS + syllable / (VOWEL_COUNT * TRAIL_COUNT),
+            ));
+            push(char_from(
+                HANGUL_VOWELS + syllable % (VOWEL_COUNT * TRAIL_COUNT) / TRAIL_COUNT,
+            ));
+            if trail > 0 {
+                push(char_from(HANGUL_TRAILS + trail));
+            }
+        } else if let Ok(i) = DECOMPOSITIONS.binary_search_by_key(&c, |&(c, _)| c) {
+            DECOMPOSITIONS[i].1.chars().for_each(&mut push);
+        } else {
+            push(c);
+        }
+    }
+
+    chars
+}
+
+// Combines each base char with the marks after it where a composed char exists.
+// A mark can't combine past another mark of the same or a lower class.
+fn compose(chars: Vec<(char, Range<usize>)>) -> Vec<(char, Range<usize>)> {
+    let mut composed: Vec<(char, Range<usize>)> = Vec::with_capacity(chars.len());
+    let mut starter: Option<usize> = None;
+    // class of the last char after the starter that didn't combine with it
+    let mut last_class: Option<u8> = None;
+
+    for (c, source) in chars {
+        let class = combining_class(c);
+        if let Some(s) = starter {
+            let blocked = last_class.is_some_and(|last| last == 0 || last >= class);
+            if let Some(both) = compose_pair(composed[s].0, c).filter(|_| !blocked) {
+                let start = composed[s].1.start.min(source.start);
+                let end = composed[s].1.end.max(source.end);
+                composed[s] = (both, start..end);
+                continue;
+            }
+        }
+
+        if class == 0 {
+            starter = Some(composed.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        composed.push((c, source));
+    }
+
+    composed
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (first as u32, second as u32);
+    let lead = a.wrapping_sub(HANGUL_LEADS);
+    let vowel = b.wrapping_sub(HANGUL_VOWELS);
+    let syllable = a.wrapping_sub(HANGUL_SYLLABLES);
+    let trail = b.wrapping_sub(HANGUL_TRAILS);
+
+    if lead < LEAD_COUNT && vowel < VOWEL_COUNT {
+        Some(char_from(
+            HANGUL_SYLLABLES + (lead * VOWEL_COUNT + vowel) * TRAIL_COUNT,
+        ))
+    } else if syllable < SYLLABLE_COUNT
+        && syllable % TRAIL_COUNT == 0
+        && (1..TRAIL_COUNT).contains(&trail)
+    {
+        Some(char_from(a + trail))
+    } else {
+        COMPOSITIONS
+            .binary_search_by_key(&(first, second), |&(a, b, _)| (a, b))
+            .ok()
+            .map(|i| COMPOSITIONS[i].2)
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    // nothing below U+0300 combines
+    if c < '\u{300}' {
+        return 0;
+    }
+
+    let i = COMBINING_CLASSES.partition_point(|&(_, last, _)| last < c);
+    match COMBINING_CLASSES.get(i) {
+        Some(&(first, _, class)) if first <= c => class,
+        _ => 0,
+    }
+}
+
+// only called with code points worked out from valid Hangul
+fn char_from(code: u32) -> char {
+    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
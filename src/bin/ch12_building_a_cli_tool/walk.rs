@@ -0,0 +1,314 @@
+// Turns the paths given to minigrep into a list of files to search.
+// Directories are walked recursively, skipping hidden entries and anything
+// listed in a `.gitignore` or `.ignore` file along the way.
+// `--glob` and the `--type` presets narrow down the walked files before any of them is opened.
+use super::args::ArgsError;
+use super::glob::Glob;
+use super::lib::STDIN;
+use std::fs::{self, FileType};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// File types for `--type` and `--type-not`, and the file names each one is
+pub const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"]),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs"]),
+    ("json", &["*.json"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml", "Cargo.lock"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yml", "*.yaml"]),
+];
+
+/// The `--type-list` text, ex. `rust: *.rs`
+pub fn type_list() -> String {
+    TYPES
+        .iter()
+        .map(|(name, globs)| format!("{}: {}", name, globs.join(", ")))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Which of the walked files to search
+#[derive(Debug, Default)]
+pub struct Filter {
+    // `--glob` rules, written like the lines of an ignore file, except `!` excludes
+    globs: Vec<IgnoreRule>,
+    // file names from `--type`, a file has to match one of them
+    types: Vec<Glob>,
+    // file names from `--type-not`, which are never searched
+    types_not: Vec<Glob>,
+}
+
+impl Filter {
+    /// Adds a `--glob`, ex. `*.rs` to only search Rust files or `!target` to skip a folder
+    pub fn add_glob(&mut self, pattern: &str) {
+        // the rule's `negated` is set by `!`, which here excludes instead of including again
+        self.globs.extend(IgnoreRule::parse(pattern));
+    }
+
+    /// Adds the file names of a type to search, or to skip with `negated`
+    pub fn add_type(&mut self, name: &str, negated: bool) -> Result<(), ArgsError> {
+        let (_, globs) = TYPES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(|| {
+                ArgsError::Invalid(format!("unknown file type {}, see --type-list", name))
+            })?;
+        let globs = globs.iter().map(|glob| Glob::new(glob));
+        if negated {
+            self.types_not.extend(globs);
+        } else {
+            self.types.extend(globs);
+        }
+        Ok(())
+    }
+
+    /// Checks a path, relative to the folder that's walked, with `/` as the separator
+    pub fn allows(&self, relative: &str, is_dir: bool) -> bool {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+
+        // The last glob that matches decides, like in an ignore file. Once any glob includes
+        // files, the other files are left out, but folders are still walked to find them.
+        match self
+            .globs
+            .iter()
+            .rev()
+            .find(|rule| rule.applies(relative, is_dir))
+        {
+            Some(rule) if rule.negated => return false,
+            Some(_) => {}
+            None if !is_dir && self.globs.iter().any(|rule| !rule.negated) => return false,
+            None => {}
+        }
+        if is_dir {
+            return true;
+        }
+
+        let is_type = self.types.is_empty() || self.types.iter().any(|glob| glob.is_match(name));
+        is_type && !self.types_not.iter().any(|glob| glob.is_match(name))
+    }
+}
+
+/// The files found under the paths of a search
+#[derive(Debug, Default)]
+pub struct Walked {
+    pub files: Vec<PathBuf>,
+    // the folders that couldn't be read, which were left out
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl Walked {
+    /// Prints each folder that couldn't be read, like grep -r does, and returns if there were any
+    pub fn report_errors(&self) -> bool {
+        for (path, e) in &self.errors {
+            eprintln!("{}: {}", path.display(), e);
+        }
+        !self.errors.is_empty()
+    }
+}
+
+/// Expands files and directories into a sorted list of the files the filter allows.
+/// Folders that can't be read are kept in `errors`, and the others are still walked.
+pub fn files(paths: &[&str], filter: &Filter) -> Walked {
+    let mut walked = Walked::default();
+
+    for path in paths {
+        let path = Path::new(path);
+
+        // files named on the command line are always searched,
+        // missing ones are reported when they fail to open
+        if path != Path::new(STDIN) && path.is_dir() {
+            let mut walk = Walk {
+                root: path,
+                filter,
+                ignores: vec![],
+                walked: &mut walked,
+            };
+            walk.walk(path);
+        } else {
+            walked.files.push(path.to_path_buf());
+        }
+    }
+
+    walked
+}
+
+// what's needed while walking a folder that was given on the command line
+struct Walk<'a> {
+    root: &'a Path,
+    filter: &'a Filter,
+    // ignore files from the folders above the one being walked
+    ignores: Vec<IgnoreFile>,
+    walked: &'a mut Walked,
+}
+
+impl<'a> Walk<'a> {
+    fn walk(&mut self, dir: &Path) {
+        // rules from this folder apply to everything below it
+        let parent_rules = self.ignores.len();
+        for name in IGNORE_FILES.iter() {
+            if let Some(ignore) = IgnoreFile::open(dir, name) {
+                self.ignores.push(ignore);
+            }
+        }
+
+        // sort entries so output is the same on every run
+        let mut entries = match read_entries(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.walked.errors.push((dir.to_path_buf(), e));
+                self.ignores.truncate(parent_rules);
+                return;
+            }
+        };
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (path, file_type) in entries {
+            let file_type = match file_type {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    self.walked.errors.push((path, e));
+                    continue;
+                }
+            };
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            // like grep -r, links to folders aren't followed, they can loop back to a parent
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            let is_dir = file_type.is_dir();
+
+            if hidden || is_ignored(&self.ignores, &path, is_dir) {
+                continue;
+            }
+            let relative = relative_path(&path, self.root).unwrap_or_default();
+            if !self.filter.allows(&relative, is_dir) {
+                continue;
+            }
+
+            if is_dir {
+                self.walk(&path);
+            } else {
+                self.walked.files.push(path);
+            }
+        }
+
+        self.ignores.truncate(parent_rules);
+    }
+}
+
+// the entries of a folder, each with its type or why that couldn't be found
+fn read_entries(dir: &Path) -> io::Result<Vec<(PathBuf, io::Result<FileType>)>> {
+    fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| (entry.path(), entry.file_type())))
+        .collect()
+}
+
+// ex. `src/main.rs` for `./src/main.rs` under `.`, with `/` even on Windows
+fn relative_path(path: &Path, root: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    // deeper ignore files take priority over their parents
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| ignore.matched(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// Patterns read from a single ignore file
+#[derive(Debug)]
+pub struct IgnoreFile {
+    root: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    glob: Glob,
+    // `!pattern` re-includes a path
+    negated: bool,
+    // `pattern/` only matches folders
+    dir_only: bool,
+    // `a/b` or `/a` matches relative to the ignore file instead of any file name
+    anchored: bool,
+}
+
+impl IgnoreFile {
+    fn open(dir: &Path, name: &str) -> Option<IgnoreFile> {
+        let contents = fs::read_to_string(dir.join(name)).ok()?;
+        Some(IgnoreFile::parse(dir, &contents))
+    }
+
+    pub fn parse(root: &Path, contents: &str) -> IgnoreFile {
+        IgnoreFile {
+            root: root.to_path_buf(),
+            rules: contents.lines().filter_map(IgnoreRule::parse).collect(),
+        }
+    }
+
+    /// Returns `Some(true)` if ignored, `Some(false)` if re-included, and `None` if no rule applies
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = relative_path(path, &self.root)?;
+
+        // the last matching rule wins
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.applies(&relative, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl IgnoreRule {
+    // reads one line of an ignore file, skipping blank lines and comments
+    fn parse(line: &str) -> Option<IgnoreRule> {
+        let mut pattern = line.trim_end();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return None;
+        }
+
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+
+        Some(IgnoreRule {
+            glob: Glob::new(pattern.trim_start_matches('/')),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    // checks a path relative to where the rule is from, with `/` as the separator
+    fn applies(&self, relative: &str, is_dir: bool) -> bool {
+        let target = if self.anchored {
+            relative
+        } else {
+            relative.rsplit('/').next().unwrap_or(relative)
+        };
+        (is_dir || !self.dir_only) && self.glob.is_match(target)
+    }
+}
@@ -0,0 +1,371 @@
+// Keeps searching as files change, for logs that are still being written.
+// - `--watch` polls the paths and reruns the whole search when a file's mtime, size or inode changes,
+//   or when a file is added to or removed from a folder
+// - `--follow` only searches what's appended to each file since the last poll, like `tail -f`.
+//   A file that shrinks was truncated, and one with a new inode was rotated, so both are read again
+//   from the start.
+// Both run until they're interrupted.
+// ex. $ cargo run --bin ch12_building_a_cli_tool -- --follow -n ERROR ./logs
+use super::encoding::{self, DecodeReader, Encoding};
+use super::lib::{self, Config, Searcher};
+use super::printer::{Context, Printer};
+use super::records::Separator;
+use super::walk;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How long to wait between checks for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How to keep searching once the first search is done
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Watch {
+    // search everything again
+    Rerun,
+    // only search what was added
+    Follow,
+}
+
+/// What a file looked like at the last poll
+#[derive(Debug, Clone, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    size: u64,
+    inode: u64,
+}
+
+impl Stamp {
+    fn new(metadata: &Metadata) -> Stamp {
+        Stamp {
+            modified: metadata.modified().ok(),
+            size: metadata.len(),
+            inode: inode(metadata),
+        }
+    }
+}
+
+// a new inode at the same path means the file was replaced, ex. by log rotation
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_: &Metadata) -> u64 {
+    0
+}
+
+/// Every file under the paths, with what each looked like.
+/// Files that can't be read are included without a stamp, so they change when they come back.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot(Vec<(PathBuf, Option<Stamp>)>);
+
+impl Snapshot {
+    pub fn take(config: &Config) -> io::Result<Snapshot> {
+        let walked = walk::files(&config.paths, &config.filter);
+        // the search reports it, and the snapshot changes once it can be read again
+        if let Some((_, e)) = walked.errors.into_iter().next() {
+            return Err(e);
+        }
+        Ok(Snapshot(
+            walked
+                .files
+                .into_iter()
+                .map(|file| {
+                    let stamp = fs::metadata(&file)
+                        .ok()
+                        .map(|metadata| Stamp::new(&metadata));
+                    (file, stamp)
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Runs the search, then runs it again each time a file changes
+pub fn watch(config: &Config) -> Result<i32, Box<dyn Error>> {
+    let mut last = None;
+    loop {
+        // a folder that's gone is reported by the search, then checked for again
+        let snapshot = Snapshot::take(config).ok();
+        if snapshot != last {
+            if last.is_some() {
+                eprintln!("minigrep: files changed, searching again");
+            }
+            if let Err(e) = lib::run(config) {
+                eprintln!("Application error: {}", e);
+            }
+            last = snapshot;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Searches each file, then only what's appended to it
+pub fn follow(config: &Config) -> Result<i32, Box<dyn Error>> {
+    let searcher = Searcher::new(config)?;
+    let stdout = io::stdout();
+    let mut printer = Printer::new(stdout.lock(), config, config.with_filename());
+    let mut follower = Follower::new();
+    loop {
+        follower.poll(config, &searcher, &mut printer)?;
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// where reading a file left off
+#[derive(Debug)]
+struct Position {
+    inode: u64,
+    offset: u64,
+    // found from the BOM at the start of the file, which what's appended doesn't have
+    encoding: Option<Encoding>,
+    line_number: usize,
+    // the end of what was read as UTF-8, a record that wasn't done yet
+    pending: Vec<u8>,
+    // so -m and the context lines count the whole file, not each poll on its own
+    selected: usize,
+    context: Context,
+}
+
+impl Position {
+    fn new(inode: u64) -> Position {
+        Position {
+            inode,
+            offset: 0,
+            encoding: None,
+            line_number: 1,
+            pending: vec![],
+            selected: 0,
+            context: Context::default(),
+        }
+    }
+}
+
+/// Remembers how much of each file has been searched
+#[derive(Debug, Default)]
+pub struct Follower {
+    positions: HashMap<PathBuf, Position>,
+}
+
+impl Follower {
+    pub fn new() -> Follower {
+        Follower::default()
+    }
+
+    /// Searches the records that were completed since the last poll, returns how many were selected.
+    /// A record that's still being written waits for the next poll.
+    pub fn poll<W: Write>(
+        &mut self,
+        config: &Config,
+        searcher: &Searcher,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        let mut count = 0;
+        let walked = walk::files(&config.paths, &config.filter);
+        walked.report_errors();
+        for file in walked.files {
+            match self.poll_file(&file, searcher, printer) {
+                Ok(selected) => count += selected,
+                // ex. removed while rotating, it's read from the start if it comes back
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    self.positions.remove(&file);
+                }
+                Err(e) => eprintln!("{}: {}", file.display(), e),
+            }
+        }
+        Ok(count)
+    }
+
+    fn poll_file<W: Write>(
+        &mut self,
+        file: &Path,
+        searcher: &Searcher,
+        printer: &mut Printer<W>,
+    ) -> io::Result<usize> {
+        let mut opened = File::open(file)?;
+        let stamp = Stamp::new(&opened.metadata()?);
+        let position = self
+            .positions
+            .entry(file.to_path_buf())
+            .or_insert_with(|| Position::new(stamp.inode));
+
+        if stamp.inode != position.inode || stamp.size < position.offset {
+            let why = if stamp.inode != position.inode {
+                "replaced"
+            } else {
+                "truncated"
+            };
+            eprintln!(
+                "minigrep: {}: file {}, reading from the start",
+                file.display(),
+                why
+            );
+            *position = Position::new(stamp.inode);
+        }
+        // once -m lines were selected only the context lines owed to the last one are printed
+        let limit_reached = searcher
+            .max_count
+            .is_some_and(|max| position.selected >= max);
+        if limit_reached && !position.context.owes_lines() {
+            position.offset = stamp.size;
+            position.pending.clear();
+            return Ok(0);
+        }
+        if stamp.size == position.offset {
+            return Ok(0);
+        }
+
+        let encoding = match position.encoding {
+            Some(encoding) => encoding,
+            None => {
+                let mut start = vec![];
+                (&mut opened).take(3).read_to_end(&mut start)?;
+                let (encoding, bom_len) = encoding::detect(&start, searcher.encoding);
+                position.offset = bom_len as u64;
+                position.encoding = Some(encoding);
+                encoding
+            }
+        };
+        let end = whole_chars_end(&mut opened, encoding, position.offset, stamp.size)?;
+        if end == position.offset {
+            return Ok(0);
+        }
+
+        // only the appended bytes are read and decoded,
+        // and only the last record that isn't done is kept
+        opened.seek(SeekFrom::Start(position.offset))?;
+        let appended = opened.take(end - position.offset);
+        let decoded: Box<dyn Read> = match encoding {
+            Encoding::Utf8 => Box::new(appended),
+            _ => Box::new(DecodeReader::new(appended, encoding)),
+        };
+        // copies, so an error leaves the position as it was
+        let pending = position.pending.clone();
+        let mut context = position.context.clone();
+        let mut records = BufReader::new(Complete::new(decoded, &searcher.separator, pending));
+        let mut selected = position.selected;
+        if !records.fill_buf()?.is_empty() {
+            printer.resume_file(&file.display().to_string(), context)?;
+            selected = searcher.search_decoded(
+                &mut records,
+                position.line_number,
+                position.selected,
+                printer,
+            )?;
+            printer.end_file(selected - position.selected)?;
+            context = printer.suspend_file();
+        }
+
+        let records = records.into_inner();
+        let newlines = records.newlines;
+        position.pending = records.into_pending()?;
+        position.offset = end;
+        position.line_number += newlines;
+        let count = selected - position.selected;
+        position.selected = selected;
+        position.context = context;
+        Ok(count)
+    }
+}
+
+// Where the appended bytes end, leaving out a UTF-16 char that's only partly written,
+// which is read at the next poll once the rest of it is there
+fn whole_chars_end(file: &mut File, encoding: Encoding, offset: u64, size: u64) -> io::Result<u64> {
+    if encoding != Encoding::Utf16Le && encoding != Encoding::Utf16Be {
+        return Ok(size);
+    }
+
+    let mut end = size - (size - offset) % 2;
+    if end > offset {
+        let mut unit = [0; 2];
+        file.seek(SeekFrom::Start(end - 2))?;
+        file.read_exact(&mut unit)?;
+        let unit = match encoding {
+            Encoding::Utf16Le => u16::from_le_bytes(unit),
+            _ => u16::from_be_bytes(unit),
+        };
+        // the first half of a surrogate pair
+        if (0xd800..0xdc00).contains(&unit) {
+            end -= 2;
+        }
+    }
+    Ok(end)
+}
+
+// Passes on the whole records of a reader, and holds back the bytes after the last separator,
+// a record that's still being written
+struct Complete<'a, R> {
+    reader: R,
+    end: &'a [u8],
+    held: Vec<u8>,
+    // how many bytes at the start of `held` are whole records, and how many of those were read
+    ready: usize,
+    read: usize,
+    newlines: usize,
+}
+
+impl<'a, R: Read> Complete<'a, R> {
+    // `pending` is the unfinished record from before, which comes first
+    fn new(reader: R, separator: &'a Separator, pending: Vec<u8>) -> Complete<'a, R> {
+        let end: &[u8] = match separator {
+            Separator::Line => b"\n",
+            // a paragraph is only done once a blank line follows it
+            Separator::Paragraph => b"\n\n",
+            Separator::Nul => b"\0",
+            Separator::Delimiter(delimiter) => delimiter,
+        };
+        Complete {
+            reader,
+            end,
+            held: pending,
+            ready: 0,
+            read: 0,
+            newlines: 0,
+        }
+    }
+
+    // what wasn't read, including the records a search that stopped early left
+    fn into_pending(mut self) -> io::Result<Vec<u8>> {
+        self.held.drain(..self.read);
+        self.reader.read_to_end(&mut self.held)?;
+        Ok(self.held)
+    }
+}
+
+impl<R: Read> Read for Complete<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read == self.ready {
+            self.held.drain(..self.ready);
+            self.ready = 0;
+            self.read = 0;
+
+            let mut chunk = [0; 8 * 1024];
+            let n = self.reader.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            // a separator can start in the bytes that were already held
+            let from = (self.held.len() + 1).saturating_sub(self.end.len());
+            self.held.extend_from_slice(&chunk[..n]);
+            if let Some(i) = self.held[from..]
+                .windows(self.end.len())
+                .rposition(|window| window == self.end)
+            {
+                self.ready = from + i + self.end.len();
+            }
+        }
+
+        let n = buf.len().min(self.ready - self.read);
+        buf[..n].copy_from_slice(&self.held[self.read..self.read + n]);
+        self.newlines += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        self.read += n;
+        Ok(n)
+    }
+}
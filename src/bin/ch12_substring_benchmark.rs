@@ -0,0 +1,122 @@
+// Compares minigrep's literal search against the `contains` and `to_lowercase` search it replaced.
+// cargo run --release --bin ch12_substring_benchmark
+extern crate rand;
+
+#[path = "ch12_building_a_cli_tool/substring.rs"]
+mod substring;
+// only case folding is used here
+#[allow(dead_code)]
+#[path = "ch12_building_a_cli_tool/unicode.rs"]
+mod unicode;
+#[path = "ch12_building_a_cli_tool/unicode_tables.rs"]
+mod unicode_tables;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use substring::Finder;
+
+const LINES: usize = 100_000;
+const RUNS: usize = 5;
+
+fn main() {
+    let ascii = [
+        "the", "frog", "bog", "public", "dreary", "somebody", "nobody", "livelong",
+    ];
+    let accented = [
+        "café", "straße", "naïve", "über", "frog", "señor", "bog", "Ærø",
+    ];
+
+    let corpora = [
+        ("lowercase ASCII", corpus(&ascii, false)),
+        ("mixed case ASCII", corpus(&ascii, true)),
+        ("mixed case Unicode", corpus(&accented, true)),
+    ];
+
+    println!(
+        "{:<20} {:<14} {:>12} {:>12} {:>8}",
+        "corpus", "search", "naive MB/s", "finder MB/s", "speedup"
+    );
+    for (name, lines) in &corpora {
+        let bytes: usize = lines.iter().map(|line| line.len() + 1).sum();
+        for &(query, ignore_case) in &[("needle", false), ("NeEdLe", true)] {
+            let lower = query.to_lowercase();
+            let naive = time(|| {
+                lines
+                    .iter()
+                    .filter(|line| {
+                        if ignore_case {
+                            line.to_lowercase().contains(&lower)
+                        } else {
+                            line.contains(query)
+                        }
+                    })
+                    .count()
+            });
+
+            let finder = Finder::new(query, ignore_case);
+            let fast = time(|| {
+                lines
+                    .iter()
+                    .filter(|line| finder.find_at(line, 0).is_some())
+                    .count()
+            });
+
+            // both have to find the same lines for the comparison to mean anything
+            assert_eq!(naive.1, fast.1, "{} {}", name, query);
+            println!(
+                "{:<20} {:<14} {:>12.1} {:>12.1} {:>7.2}x",
+                name,
+                if ignore_case {
+                    "ignore case"
+                } else {
+                    "case sensitive"
+                },
+                throughput(bytes, naive.0),
+                throughput(bytes, fast.0),
+                naive.0.as_secs_f64() / fast.0.as_secs_f64()
+            );
+        }
+    }
+}
+
+// lines of random words, with the query planted in about 1 in 50 lines
+fn corpus(words: &[&str], mixed_case: bool) -> Vec<String> {
+    let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+
+    (0..LINES)
+        .map(|_| {
+            let mut line = vec![];
+            for _ in 0..rng.gen_range(5, 15) {
+                let word = if rng.gen_range(0, 500) == 0 {
+                    "needle"
+                } else {
+                    words[rng.gen_range(0, words.len())]
+                };
+
+                if mixed_case && rng.gen() {
+                    line.push(word.to_uppercase());
+                } else {
+                    line.push(word.to_string());
+                }
+            }
+            line.join(" ")
+        })
+        .collect()
+}
+
+// the fastest of a few runs, with what the search returned
+fn time<F: Fn() -> usize>(search: F) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut result = 0;
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        result = black_box(search());
+        best = best.min(started.elapsed());
+    }
+    (best, result)
+}
+
+fn throughput(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / 1_000_000.0 / elapsed.as_secs_f64()
+}